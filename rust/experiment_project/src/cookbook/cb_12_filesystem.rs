@@ -166,26 +166,292 @@ mod detect_loops {
     }
 }
 
-mod find_duplicates {
-
-    use std::collections::HashMap;
+pub mod find_duplicates {
+
+    use crate::cookbook::cb_4_concurrency::{compute_digest, DigestAlgo};
+    use data_encoding::HEXLOWER;
+    use rayon::prelude::*;
+    use ring::digest::{digest, SHA256};
+    use std::collections::{HashMap, HashSet};
+    use std::fs::{self, File};
+    use std::io::{Error, Read};
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
     use walkdir::WalkDir;
 
+    const PARTIAL_LEN: u64 = 4096;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Action {
+        Report,
+        Hardlink,
+        Delete,
+    }
+
+    #[derive(Debug)]
+    pub struct DuplicateGroup {
+        pub size: u64,
+        pub digest: Vec<u8>,
+        /// sorted, the first file is the one that is kept
+        pub files: Vec<PathBuf>,
+    }
+
+    impl DuplicateGroup {
+        pub fn wasted_bytes(&self) -> u64 {
+            self.size * (self.files.len() as u64 - 1)
+        }
+    }
+
     pub fn main() {
-        let mut filenames = HashMap::new();
+        if let Err(e) = run(Path::new("."), Action::Report, true) {
+            eprintln!("dedup: {}", e);
+        }
+    }
 
-        for entry in WalkDir::new(".")
+    /// Reports duplicate groups and optionally replaces the extra copies
+    /// with hardlinks or deletes them. With `dry_run` nothing is modified.
+    /// Fails without touching anything if part of the tree can't be read.
+    pub fn run(root: &Path, action: Action, dry_run: bool) -> Result<(), Error> {
+        let groups = find_duplicates(root)?;
+
+        for group in &groups {
+            println!(
+                "{} bytes wasted by {} copies of {} bytes (sha256 {}):",
+                group.wasted_bytes(),
+                group.files.len(),
+                group.size,
+                HEXLOWER.encode(&group.digest)
+            );
+            group
+                .files
+                .iter()
+                .for_each(|f| println!("    {}", f.display()));
+        }
+        println!(
+            "{} duplicate groups, {} bytes wasted",
+            groups.len(),
+            groups.iter().map(|g| g.wasted_bytes()).sum::<u64>()
+        );
+
+        if action == Action::Report {
+            return Ok(());
+        }
+        for group in &groups {
+            let (keep, extra) = group.files.split_first().unwrap();
+            for dup in extra {
+                let res = match (action, dry_run) {
+                    (Action::Hardlink, true) => {
+                        println!("would link {} -> {}", dup.display(), keep.display());
+                        Ok(())
+                    }
+                    (Action::Delete, true) => {
+                        println!("would delete {}", dup.display());
+                        Ok(())
+                    }
+                    (Action::Hardlink, false) => replace_with_hardlink(keep, dup),
+                    (Action::Delete, false) => fs::remove_file(dup),
+                    (Action::Report, _) => Ok(()),
+                };
+                if let Err(e) = res {
+                    eprintln!("{}: {}", dup.display(), e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Groups files by size, then by the hash of the first 4 KiB and
+    /// finally by the full SHA-256, computed in parallel. A directory that
+    /// can't be read is an error, not a tree without duplicates.
+    pub fn find_duplicates(root: &Path) -> Result<Vec<DuplicateGroup>, Error> {
+        let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        // hardlinks to the same inode do not waste space
+        let mut seen_inodes = HashSet::new();
+
+        for entry in WalkDir::new(root) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let meta = entry.metadata()?;
+            if meta.len() > 0 && seen_inodes.insert((meta.dev(), meta.ino())) {
+                by_size
+                    .entry(meta.len())
+                    .or_default()
+                    .push(entry.into_path());
+            }
+        }
+
+        let candidates: Vec<_> = by_size
             .into_iter()
-            .filter_map(Result::ok)
-            .filter(|e| !e.file_type().is_dir())
-        {
-            let f_name = String::from(entry.file_name().to_string_lossy());
-            let counter = filenames.entry(f_name.clone()).or_insert(0);
-            *counter += 1;
+            .filter(|(_, files)| files.len() > 1)
+            .flat_map(|(size, files)| files.into_iter().map(move |f| ((size, vec![]), f)))
+            .collect();
+
+        let partial = group_by_hash(candidates, |_, path| partial_hash(path));
+        let full = group_by_hash(partial, |(size, partial), path| {
+            if *size <= PARTIAL_LEN {
+                // the partial hash already covered the whole content
+                return Ok(partial.clone());
+            }
+            let (digests, _) = compute_digest(path.to_owned(), &[DigestAlgo::Sha256])?;
+            Ok(digests.into_iter().next().unwrap())
+        });
 
-            if *counter == 2 {
-                println!("{}", f_name);
+        let mut groups: HashMap<Key, Vec<PathBuf>> = HashMap::new();
+        for (key, path) in full {
+            groups.entry(key).or_default().push(path);
+        }
+        let mut groups: Vec<_> = groups
+            .into_iter()
+            .map(|((size, digest), mut files)| {
+                files.sort();
+                DuplicateGroup {
+                    size,
+                    digest,
+                    files,
+                }
+            })
+            .collect();
+        groups.sort_by(|a, b| {
+            b.wasted_bytes()
+                .cmp(&a.wasted_bytes())
+                .then_with(|| a.files.cmp(&b.files))
+        });
+        Ok(groups)
+    }
+
+    /// file size and content hash
+    type Key = (u64, Vec<u8>);
+
+    // rehashes every candidate in parallel and keeps only keys shared by several files
+    fn group_by_hash<F>(candidates: Vec<(Key, PathBuf)>, hash: F) -> Vec<(Key, PathBuf)>
+    where
+        F: Fn(&Key, &Path) -> Result<Vec<u8>, Error> + Sync,
+    {
+        let hashed: Vec<_> = candidates
+            .into_par_iter()
+            .filter_map(|(key, path)| match hash(&key, &path) {
+                Ok(digest) => Some(((key.0, digest), path)),
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+
+        let mut counts: HashMap<&Key, usize> = HashMap::new();
+        hashed
+            .iter()
+            .for_each(|(key, _)| *counts.entry(key).or_default() += 1);
+        let shared: HashSet<Key> = counts
+            .into_iter()
+            .filter(|(_, n)| *n > 1)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        hashed
+            .into_iter()
+            .filter(|(key, _)| shared.contains(key))
+            .collect()
+    }
+
+    fn partial_hash(path: &Path) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::with_capacity(PARTIAL_LEN as usize);
+        File::open(path)?.take(PARTIAL_LEN).read_to_end(&mut buf)?;
+        Ok(digest(&SHA256, &buf).as_ref().to_vec())
+    }
+
+    // links under a temporary name first, so the duplicate is never missing
+    fn replace_with_hardlink(keep: &Path, dup: &Path) -> Result<(), Error> {
+        let mut tmp = dup.as_os_str().to_owned();
+        tmp.push(".dedup-tmp");
+        fs::hard_link(keep, &tmp)?;
+        fs::rename(&tmp, dup).inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_find_and_link_duplicates() {
+            let dir = tempfile::tempdir().unwrap();
+            let root = dir.path();
+            let big: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+            let mut big_other = big.clone();
+            *big_other.last_mut().unwrap() ^= 1;
+
+            fs::create_dir(root.join("sub")).unwrap();
+            fs::write(root.join("a.txt"), b"same content").unwrap();
+            fs::write(root.join("sub/b.txt"), b"same content").unwrap();
+            fs::write(root.join("c.txt"), b"other content").unwrap();
+            fs::write(root.join("big1"), &big).unwrap();
+            fs::write(root.join("big2"), &big).unwrap();
+            // same size and same first 4 KiB, differs only at the end
+            fs::write(root.join("big3"), &big_other).unwrap();
+
+            let groups = find_duplicates(root).unwrap();
+            assert_eq!(groups.len(), 2);
+            assert_eq!(groups[0].files, vec![root.join("big1"), root.join("big2")]);
+            assert_eq!(groups[0].wasted_bytes(), 10_000);
+            assert_eq!(
+                groups[1].files,
+                vec![root.join("a.txt"), root.join("sub/b.txt")]
+            );
+
+            run(root, Action::Hardlink, true).unwrap();
+            assert_eq!(find_duplicates(root).unwrap().len(), 2);
+
+            run(root, Action::Hardlink, false).unwrap();
+            assert!(find_duplicates(root).unwrap().is_empty());
+            let ino = |p: &str| fs::metadata(root.join(p)).unwrap().ino();
+            assert_eq!(ino("big1"), ino("big2"));
+            assert_eq!(fs::read(root.join("sub/b.txt")).unwrap(), b"same content");
+        }
+
+        fn files_and_contents(root: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+            let mut files: Vec<_> = WalkDir::new(root)
+                .into_iter()
+                .map(|e| e.unwrap().into_path())
+                .filter(|p| p.is_file())
+                .map(|p| {
+                    let content = fs::read(&p).unwrap();
+                    (p, content)
+                })
+                .collect();
+            files.sort();
+            files
+        }
+
+        #[test]
+        fn test_delete_duplicates() {
+            let dir = tempfile::tempdir().unwrap();
+            let root = dir.path();
+            fs::create_dir(root.join("sub")).unwrap();
+            for copy in ["a.txt", "b.txt", "sub/c.txt"] {
+                fs::write(root.join(copy), b"same content").unwrap();
             }
+            fs::write(root.join("unique.txt"), b"other content").unwrap();
+            let before = files_and_contents(root);
+
+            run(root, Action::Delete, true).unwrap();
+            assert_eq!(files_and_contents(root), before);
+
+            run(root, Action::Delete, false).unwrap();
+            assert_eq!(
+                files_and_contents(root),
+                vec![
+                    (root.join("a.txt"), b"same content".to_vec()),
+                    (root.join("unique.txt"), b"other content".to_vec()),
+                ]
+            );
+            assert!(find_duplicates(root).unwrap().is_empty());
+
+            assert!(find_duplicates(&root.join("missing")).is_err());
+            assert!(run(&root.join("missing"), Action::Delete, false).is_err());
         }
     }
 }
@@ -21,3 +21,354 @@ mod server {
         Ok(())
     }
 }
+
+/// Encrypted two-party chat. Both sides send an ephemeral X25519 public key,
+/// HKDF turns the shared secret into one ChaCha20-Poly1305 key per direction
+/// and every message travels as a frame
+///
+/// ```text
+/// length u32 BE | counter u64 BE | ciphertext + tag
+/// ```
+///
+/// The counter is the nonce and the AAD, the receiver only accepts the next
+/// one in line, so replayed, dropped or reordered frames are rejected.
+/// The keys are not authenticated, someone in the middle could run a
+/// handshake with each side. Both sides print a fingerprint of the two
+/// public keys, compare them out of band if it matters.
+pub mod chat {
+    use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
+    use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519};
+    use ring::digest::{digest, SHA256};
+    use ring::hkdf::{Salt, HKDF_SHA256};
+    use ring::rand::SystemRandom;
+    use std::io::{self, BufRead, Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::thread;
+
+    /// Longest accepted frame, a peer can't make us allocate more than that
+    pub const MAX_FRAME_LEN: usize = 1 << 20;
+
+    const PUBLIC_KEY_LEN: usize = 32;
+    const COUNTER_LEN: usize = 8;
+    const FINGERPRINT_LEN: usize = 10;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Role {
+        /// the side which connected
+        Client,
+        /// the side which accepted the connection
+        Server,
+    }
+
+    pub struct SessionKeys {
+        pub send: LessSafeKey,
+        pub recv: LessSafeKey,
+        /// the same on both sides unless someone sits in the middle
+        pub fingerprint: String,
+    }
+
+    fn invalid_data(msg: String) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    }
+
+    fn direction_key(salt: &Salt, shared: &[u8], info: &[u8]) -> LessSafeKey {
+        let prk = salt.extract(shared);
+        let info = [info];
+        let okm = prk
+            .expand(&info, &CHACHA20_POLY1305)
+            .expect("HKDF output fits the key length");
+        LessSafeKey::new(UnboundKey::from(okm))
+    }
+
+    /// Swaps ephemeral public keys over `stream` and derives the session keys
+    pub fn handshake<S: Read + Write>(stream: &mut S, role: Role) -> io::Result<SessionKeys> {
+        let rng = SystemRandom::new();
+        let private = EphemeralPrivateKey::generate(&X25519, &rng)
+            .map_err(|_| io::Error::other("can't generate an X25519 key"))?;
+        let public = private
+            .compute_public_key()
+            .map_err(|_| io::Error::other("can't compute the X25519 public key"))?;
+
+        stream.write_all(public.as_ref())?;
+        stream.flush()?;
+        let mut peer = [0u8; PUBLIC_KEY_LEN];
+        stream.read_exact(&mut peer)?;
+
+        // both keys go into the salt, so the session is bound to this exchange
+        let (client, server) = match role {
+            Role::Client => (public.as_ref(), &peer[..]),
+            Role::Server => (&peer[..], public.as_ref()),
+        };
+        let keys = [client, server].concat();
+        let salt = Salt::new(HKDF_SHA256, &keys);
+        let fingerprint = digest(&SHA256, &keys).as_ref()[..FINGERPRINT_LEN]
+            .chunks(2)
+            .map(|pair| pair.iter().map(|b| format!("{:02x}", b)).collect())
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        agreement::agree_ephemeral(
+            private,
+            &UnparsedPublicKey::new(&X25519, peer),
+            invalid_data("invalid X25519 public key from the peer".to_string()),
+            |shared| {
+                let to_server = direction_key(&salt, shared, b"playground chat client to server");
+                let to_client = direction_key(&salt, shared, b"playground chat server to client");
+                Ok(match role {
+                    Role::Client => SessionKeys {
+                        send: to_server,
+                        recv: to_client,
+                        fingerprint,
+                    },
+                    Role::Server => SessionKeys {
+                        send: to_client,
+                        recv: to_server,
+                        fingerprint,
+                    },
+                })
+            },
+        )
+    }
+
+    fn nonce(counter: u64) -> Nonce {
+        let mut nonce = [0u8; aead::NONCE_LEN];
+        nonce[aead::NONCE_LEN - COUNTER_LEN..].copy_from_slice(&counter.to_be_bytes());
+        Nonce::assume_unique_for_key(nonce)
+    }
+
+    pub struct Sender<W> {
+        writer: W,
+        key: LessSafeKey,
+        counter: u64,
+    }
+
+    impl<W: Write> Sender<W> {
+        pub fn new(writer: W, key: LessSafeKey) -> Self {
+            Sender {
+                writer,
+                key,
+                counter: 0,
+            }
+        }
+
+        pub fn send(&mut self, msg: &[u8]) -> io::Result<()> {
+            let len = COUNTER_LEN + msg.len() + CHACHA20_POLY1305.tag_len();
+            if len > MAX_FRAME_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("message of {} bytes is too long", msg.len()),
+                ));
+            }
+            // a repeated nonce would leak the plaintexts, so stop before the counter wraps
+            let counter = self.counter;
+            self.counter = counter
+                .checked_add(1)
+                .ok_or_else(|| io::Error::other("message counter exhausted"))?;
+
+            let mut frame = Vec::with_capacity(4 + len);
+            frame.extend_from_slice(&(len as u32).to_be_bytes());
+            frame.extend_from_slice(&counter.to_be_bytes());
+            let mut body = msg.to_vec();
+            self.key
+                .seal_in_place_append_tag(
+                    nonce(counter),
+                    Aad::from(counter.to_be_bytes()),
+                    &mut body,
+                )
+                .map_err(|_| io::Error::other("encryption failed"))?;
+            frame.extend_from_slice(&body);
+
+            self.writer.write_all(&frame)?;
+            self.writer.flush()
+        }
+
+        pub fn into_inner(self) -> W {
+            self.writer
+        }
+    }
+
+    pub struct Receiver<R> {
+        reader: R,
+        key: LessSafeKey,
+        counter: u64,
+    }
+
+    impl<R: Read> Receiver<R> {
+        pub fn new(reader: R, key: LessSafeKey) -> Self {
+            Receiver {
+                reader,
+                key,
+                counter: 0,
+            }
+        }
+
+        /// The next message, `None` once the peer closed the connection
+        pub fn recv(&mut self) -> io::Result<Option<Vec<u8>>> {
+            let mut len = [0u8; 4];
+            match self.reader.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            let len = u32::from_be_bytes(len) as usize;
+            if !(COUNTER_LEN + CHACHA20_POLY1305.tag_len()..=MAX_FRAME_LEN).contains(&len) {
+                return Err(invalid_data(format!("invalid frame length {}", len)));
+            }
+            let mut frame = vec![0u8; len];
+            self.reader.read_exact(&mut frame)?;
+
+            let (counter, body) = frame.split_at_mut(COUNTER_LEN);
+            let counter = u64::from_be_bytes(counter.try_into().unwrap());
+            if counter != self.counter {
+                return Err(invalid_data(format!(
+                    "replayed or out of order message {}, expected {}",
+                    counter, self.counter
+                )));
+            }
+            let plain = self
+                .key
+                .open_in_place(nonce(counter), Aad::from(counter.to_be_bytes()), body)
+                .map_err(|_| invalid_data(format!("message {} failed authentication", counter)))?;
+            self.counter += 1;
+            Ok(Some(plain.to_vec()))
+        }
+    }
+
+    /// Runs the handshake and splits the connection into its two directions,
+    /// the fingerprint of the handshake comes along
+    pub fn secure(
+        mut stream: TcpStream,
+        role: Role,
+    ) -> io::Result<(Sender<TcpStream>, Receiver<TcpStream>, String)> {
+        let keys = handshake(&mut stream, role)?;
+        let reader = stream.try_clone()?;
+        Ok((
+            Sender::new(stream, keys.send),
+            Receiver::new(reader, keys.recv),
+            keys.fingerprint,
+        ))
+    }
+
+    /// Interactive chat, stdin lines go to the peer and its messages are printed
+    pub fn run(stream: TcpStream, role: Role) -> io::Result<()> {
+        let peer = stream.peer_addr()?;
+        let (mut sender, mut receiver, fingerprint) = secure(stream, role)?;
+        println!("Secure session with {}, end with Ctrl-D", peer);
+        println!("Fingerprint: {}", fingerprint);
+
+        let printer = thread::spawn(move || -> io::Result<()> {
+            while let Some(msg) = receiver.recv()? {
+                println!("{}: {}", peer, String::from_utf8_lossy(&msg));
+            }
+            println!("{} left", peer);
+            Ok(())
+        });
+        for line in io::stdin().lock().lines() {
+            if sender.send(line?.as_bytes()).is_err() {
+                break;
+            }
+        }
+        // closing our side ends the peer's session, and with it ours
+        sender.into_inner().shutdown(std::net::Shutdown::Write)?;
+        printer.join().expect("printer thread panicked")
+    }
+
+    pub fn listen(addr: SocketAddr) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        println!("Waiting for a chat partner on {}", listener.local_addr()?);
+        let (stream, _) = listener.accept()?;
+        run(stream, Role::Server)
+    }
+
+    pub fn connect(addr: SocketAddr) -> io::Result<()> {
+        run(TcpStream::connect(addr)?, Role::Client)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::io::Cursor;
+
+        // handshake over a real loopback connection, returns (client, server) keys
+        fn loopback_keys() -> (SessionKeys, SessionKeys) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                handshake(&mut stream, Role::Server).unwrap()
+            });
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let client = handshake(&mut stream, Role::Client).unwrap();
+            (client, server.join().unwrap())
+        }
+
+        #[test]
+        fn test_chat_over_loopback() {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                let (mut tx, mut rx, fingerprint) = secure(stream, Role::Server).unwrap();
+                while let Some(msg) = rx.recv().unwrap() {
+                    let mut reply = b"echo ".to_vec();
+                    reply.extend_from_slice(&msg);
+                    tx.send(&reply).unwrap();
+                }
+                fingerprint
+            });
+
+            let (mut tx, mut rx, fingerprint) =
+                secure(TcpStream::connect(addr).unwrap(), Role::Client).unwrap();
+            assert_eq!(fingerprint.len(), 24);
+            for msg in ["hello", "", "how are you?"] {
+                tx.send(msg.as_bytes()).unwrap();
+                assert_eq!(
+                    rx.recv().unwrap().unwrap(),
+                    format!("echo {}", msg).as_bytes()
+                );
+            }
+            tx.into_inner().shutdown(std::net::Shutdown::Write).unwrap();
+            assert_eq!(server.join().unwrap(), fingerprint);
+            assert_eq!(rx.recv().unwrap(), None);
+        }
+
+        #[test]
+        fn test_replay_and_tampering() {
+            let (client, server) = loopback_keys();
+            let first_fingerprint = client.fingerprint;
+            let mut tx = Sender::new(Vec::new(), client.send);
+            tx.send(b"first").unwrap();
+            let first = tx.into_inner();
+
+            // the same frame twice
+            let mut rx = Receiver::new(Cursor::new(first.repeat(2)), server.recv);
+            assert_eq!(rx.recv().unwrap().unwrap(), b"first");
+            let err = rx.recv().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().contains("replayed"));
+
+            // a flipped ciphertext bit
+            let (client, server) = loopback_keys();
+            let mut tx = Sender::new(Vec::new(), client.send);
+            tx.send(b"first").unwrap();
+            let mut frame = tx.into_inner();
+            *frame.last_mut().unwrap() ^= 1;
+            let mut rx = Receiver::new(Cursor::new(frame), server.recv);
+            assert!(rx
+                .recv()
+                .unwrap_err()
+                .to_string()
+                .contains("authentication"));
+
+            // the keys of one direction don't open the other, and every
+            // session has its own fingerprint
+            let (client, server) = loopback_keys();
+            assert_eq!(client.fingerprint, server.fingerprint);
+            assert_ne!(client.fingerprint, first_fingerprint);
+            let mut tx = Sender::new(Vec::new(), client.send);
+            tx.send(b"first").unwrap();
+            let mut rx = Receiver::new(Cursor::new(tx.into_inner()), client.recv);
+            assert!(rx.recv().is_err());
+        }
+    }
+}
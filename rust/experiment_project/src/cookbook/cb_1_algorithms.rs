@@ -69,19 +69,12 @@ pub fn random_experiments() {
             .map(char::from)
             .collect();
         println!("Random string: {rand_string}");
-        // random password
-        const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
-                            abcdefghijklmnopqrstuvwxyz\
-                            0123456789)(*&^%$#@!~";
-        const PASSWORD_LEN: usize = 30;
-        let mut rng = rand::thread_rng();
-
-        let password: String = (0..PASSWORD_LEN)
-            .map(|_| {
-                let idx = rng.gen_range(0..CHARSET.len());
-                CHARSET[idx] as char
-            })
-            .collect();
+        // random password, from the OS generator rather than thread_rng
+        let policy = super::cb_1_passgen::PasswordPolicy {
+            length: 30,
+            ..Default::default()
+        };
+        let password = policy.generate().unwrap();
 
         println!("Random password: {:?}", password);
     }
@@ -0,0 +1,263 @@
+// Password and passphrase generator, the grown-up version of the random
+// password in cb_1_algorithms::random_experiments. Everything is drawn from
+// OsRng, the operating system's cryptographically secure generator.
+
+use rand::rngs::OsRng;
+use rand::Rng;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Characters that are easy to mix up when read or typed
+pub const AMBIGUOUS: &str = "Il1|O0o`'\"";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CharClass {
+    Lower,
+    Upper,
+    Digit,
+    Symbol,
+}
+
+impl CharClass {
+    pub const ALL: [CharClass; 4] = [
+        CharClass::Lower,
+        CharClass::Upper,
+        CharClass::Digit,
+        CharClass::Symbol,
+    ];
+
+    pub fn chars(&self) -> &'static str {
+        match self {
+            CharClass::Lower => "abcdefghijklmnopqrstuvwxyz",
+            CharClass::Upper => "ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+            CharClass::Digit => "0123456789",
+            CharClass::Symbol => "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~",
+        }
+    }
+}
+
+impl FromStr for CharClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lower" => Ok(CharClass::Lower),
+            "upper" => Ok(CharClass::Upper),
+            "digit" => Ok(CharClass::Digit),
+            "symbol" => Ok(CharClass::Symbol),
+            _ => Err(format!("unknown character class '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub length: usize,
+    /// characters are drawn from these classes and every one of them occurs
+    pub classes: Vec<CharClass>,
+    pub exclude_ambiguous: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            length: 20,
+            classes: CharClass::ALL.to_vec(),
+            exclude_ambiguous: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    // the characters of every requested class, minus the excluded ones
+    fn alphabets(&self) -> Vec<Vec<char>> {
+        let classes: BTreeSet<_> = self.classes.iter().collect();
+        classes
+            .into_iter()
+            .map(|c| {
+                c.chars()
+                    .chars()
+                    .filter(|ch| !(self.exclude_ambiguous && AMBIGUOUS.contains(*ch)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn check(&self) -> Result<Vec<Vec<char>>, String> {
+        let alphabets = self.alphabets();
+        if alphabets.is_empty() {
+            return Err("no character classes selected".to_string());
+        }
+        if self.length < alphabets.len() {
+            return Err(format!(
+                "a password of {} characters can't contain {} character classes",
+                self.length,
+                alphabets.len()
+            ));
+        }
+        Ok(alphabets)
+    }
+
+    /// Draws characters uniformly from all the classes and starts over until
+    /// every class occurs. Unlike placing one character of each class first,
+    /// this keeps every valid password equally likely.
+    pub fn generate(&self) -> Result<String, String> {
+        let alphabets = self.check()?;
+        let all: Vec<char> = alphabets.concat();
+        loop {
+            let password: Vec<char> = (0..self.length)
+                .map(|_| all[OsRng.gen_range(0..all.len())])
+                .collect();
+            if alphabets
+                .iter()
+                .all(|a| password.iter().any(|ch| a.contains(ch)))
+            {
+                return Ok(password.into_iter().collect());
+            }
+        }
+    }
+
+    /// log2 of the number of passwords the policy allows, counted with
+    /// inclusion-exclusion over the classes that could be missing
+    pub fn entropy_bits(&self) -> Result<f64, String> {
+        let sizes: Vec<usize> = self.check()?.iter().map(Vec::len).collect();
+        let n: usize = sizes.iter().sum();
+        let length = self.length as i32;
+
+        // fraction of all n^length strings that miss at least one class
+        let mut missing = 0.0;
+        for subset in 1..(1u32 << sizes.len()) {
+            let left: usize = sizes
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| subset & (1 << i) == 0)
+                .map(|(_, s)| s)
+                .sum();
+            let sign = if subset.count_ones() % 2 == 1 {
+                1.0
+            } else {
+                -1.0
+            };
+            missing += sign * (left as f64 / n as f64).powi(length);
+        }
+        Ok(self.length as f64 * (n as f64).log2() + (1.0 - missing).log2())
+    }
+}
+
+/// Diceware style passphrase from a word list
+#[derive(Debug, Clone)]
+pub struct Passphrase {
+    pub words: Vec<String>,
+}
+
+impl Passphrase {
+    /// One word per line. Diceware lists prefix each word with its dice
+    /// roll, so only the last field of a line is used. Duplicates are dropped,
+    /// they would only make the entropy estimate lie.
+    pub fn from_word_list(text: &str) -> Result<Passphrase, String> {
+        let words: BTreeSet<String> = text
+            .lines()
+            .filter_map(|l| l.split_whitespace().last())
+            .map(str::to_string)
+            .collect();
+        if words.len() < 2 {
+            return Err("the word list needs at least two different words".to_string());
+        }
+        Ok(Passphrase {
+            words: words.into_iter().collect(),
+        })
+    }
+
+    pub fn from_file(path: &Path) -> io::Result<Passphrase> {
+        Self::from_word_list(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn generate(&self, count: usize, separator: &str) -> String {
+        (0..count)
+            .map(|_| self.words[OsRng.gen_range(0..self.words.len())].as_str())
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    pub fn entropy_bits(&self, count: usize) -> f64 {
+        count as f64 * (self.words.len() as f64).log2()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_policy() {
+        let policy = PasswordPolicy {
+            length: 12,
+            exclude_ambiguous: true,
+            ..Default::default()
+        };
+        for _ in 0..200 {
+            let password = policy.generate().unwrap();
+            assert_eq!(password.chars().count(), 12);
+            assert!(!password.chars().any(|c| AMBIGUOUS.contains(c)));
+            for class in CharClass::ALL {
+                assert!(password.chars().any(|c| class.chars().contains(c)));
+            }
+        }
+
+        let digits = PasswordPolicy {
+            length: 3,
+            classes: vec![CharClass::Digit],
+            exclude_ambiguous: true,
+        };
+        assert!(digits
+            .generate()
+            .unwrap()
+            .chars()
+            .all(|c| "23456789".contains(c)));
+
+        let too_short = PasswordPolicy {
+            length: 3,
+            ..Default::default()
+        };
+        assert!(too_short.generate().is_err());
+    }
+
+    #[test]
+    fn test_entropy() {
+        // 10 digits, 6 characters: exactly 10^6 passwords
+        let digits = PasswordPolicy {
+            length: 6,
+            classes: vec![CharClass::Digit, CharClass::Digit],
+            exclude_ambiguous: false,
+        };
+        assert!((digits.entropy_bits().unwrap() - 6.0 * 10f64.log2()).abs() < 1e-9);
+
+        // lower + digit, 2 characters: 36^2 minus the 26^2 + 10^2 one-class ones
+        let mixed = PasswordPolicy {
+            length: 2,
+            classes: vec![CharClass::Lower, CharClass::Digit],
+            exclude_ambiguous: false,
+        };
+        let expected = ((36 * 36 - 26 * 26 - 10 * 10) as f64).log2();
+        assert!((mixed.entropy_bits().unwrap() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_passphrase() {
+        let list = "11111\tapple\n11112\tbanana\n11113 cherry\n\ndate\napple\n";
+        let words = Passphrase::from_word_list(list).unwrap();
+        assert_eq!(words.words, vec!["apple", "banana", "cherry", "date"]);
+        assert_eq!(words.entropy_bits(5), 10.0);
+
+        let phrase = words.generate(5, "-");
+        let parts: Vec<_> = phrase.split('-').collect();
+        assert_eq!(parts.len(), 5);
+        assert!(parts.iter().all(|w| words.words.iter().any(|x| x == w)));
+
+        assert!(Passphrase::from_word_list("only\nonly\n").is_err());
+    }
+}
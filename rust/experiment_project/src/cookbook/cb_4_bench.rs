@@ -0,0 +1,757 @@
+// Runs the same workloads on top of different concurrency primitives, to help
+// choosing between them. Every workload is split into the same independent
+// tasks, only the way the tasks are handed out to the threads differs.
+
+use super::cb_4_concurrency::fractal::{colour, escape_time, FractalOptions};
+use super::cb_4_concurrency::{compute_digest, DigestAlgo};
+use super::cb_6_indexed_heap::IndexedHeap;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// one `threadpool` job per task, results sent back over a channel
+    ThreadPool,
+    /// `into_par_iter` inside a dedicated rayon pool
+    Rayon,
+    /// crossbeam scoped threads pulling tasks from a shared channel
+    Crossbeam,
+    /// `std::thread::spawn`, every thread gets a contiguous slice of the tasks
+    StdThreads,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 4] = [
+        Backend::ThreadPool,
+        Backend::Rayon,
+        Backend::Crossbeam,
+        Backend::StdThreads,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::ThreadPool => "threadpool",
+            Backend::Rayon => "rayon",
+            Backend::Crossbeam => "crossbeam",
+            Backend::StdThreads => "std",
+        }
+    }
+
+    /// Applies `f` to every task on `threads` threads, results keep the task order
+    pub fn run<T, R, F>(&self, threads: usize, tasks: Vec<T>, f: F) -> Vec<R>
+    where
+        T: Send + 'static,
+        R: Send + 'static,
+        F: Fn(T) -> R + Send + Sync + 'static,
+    {
+        let threads = threads.max(1);
+        match self {
+            Backend::ThreadPool => {
+                let pool = threadpool::ThreadPool::new(threads);
+                let f = Arc::new(f);
+                let (tx, rx) = mpsc::channel();
+                let n = tasks.len();
+                for (i, task) in tasks.into_iter().enumerate() {
+                    let (tx, f) = (tx.clone(), f.clone());
+                    pool.execute(move || tx.send((i, f(task))).expect("Could not send data"));
+                }
+                drop(tx);
+                in_order(n, rx.iter())
+            }
+            Backend::Rayon => {
+                use rayon::prelude::*;
+
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .expect("Could not build the rayon pool")
+                    .install(|| tasks.into_par_iter().map(f).collect())
+            }
+            Backend::Crossbeam => {
+                let n = tasks.len();
+                let (task_tx, task_rx) = crossbeam_channel::unbounded();
+                tasks
+                    .into_iter()
+                    .enumerate()
+                    .for_each(|t| task_tx.send(t).unwrap());
+                drop(task_tx);
+
+                let (tx, rx) = crossbeam_channel::unbounded();
+                crossbeam::scope(|s| {
+                    for _ in 0..threads {
+                        let (task_rx, tx, f) = (task_rx.clone(), tx.clone(), &f);
+                        s.spawn(move |_| {
+                            for (i, task) in task_rx {
+                                tx.send((i, f(task))).expect("Could not send data");
+                            }
+                        });
+                    }
+                })
+                .expect("A worker thread panicked");
+                drop(tx);
+                in_order(n, rx.iter())
+            }
+            Backend::StdThreads => {
+                let f = Arc::new(f);
+                let chunk = tasks.len().div_ceil(threads).max(1);
+                let mut tasks = tasks.into_iter();
+                let mut handles = Vec::new();
+                loop {
+                    let slice: Vec<T> = tasks.by_ref().take(chunk).collect();
+                    if slice.is_empty() {
+                        break;
+                    }
+                    let f = f.clone();
+                    handles.push(std::thread::spawn(move || {
+                        slice.into_iter().map(|t| f(t)).collect::<Vec<_>>()
+                    }));
+                }
+                handles
+                    .into_iter()
+                    .flat_map(|h| h.join().expect("A worker thread panicked"))
+                    .collect()
+            }
+        }
+    }
+}
+
+fn in_order<R>(n: usize, results: impl Iterator<Item = (usize, R)>) -> Vec<R> {
+    let mut slots: Vec<Option<R>> = (0..n).map(|_| None).collect();
+    for (i, r) in results {
+        slots[i] = Some(r);
+    }
+    slots
+        .into_iter()
+        .map(|r| r.expect("A task did not report its result"))
+        .collect()
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Backend::ALL
+            .iter()
+            .find(|b| b.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown backend '{}'", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    /// sha256 of every file in a directory tree, one task per file
+    Hash,
+    /// Julia set, one task per row
+    Julia,
+    /// chunks sorted in parallel, then merged pairwise in parallel rounds
+    Sort,
+}
+
+impl Workload {
+    pub const ALL: [Workload; 3] = [Workload::Hash, Workload::Julia, Workload::Sort];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Workload::Hash => "hash",
+            Workload::Julia => "julia",
+            Workload::Sort => "sort",
+        }
+    }
+}
+
+impl fmt::Display for Workload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Workload {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Workload::ALL
+            .iter()
+            .find(|w| w.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown workload '{}'", s))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    pub workloads: Vec<Workload>,
+    pub backends: Vec<Backend>,
+    pub threads: Vec<usize>,
+    /// every measurement is the best of this many runs
+    pub repeat: usize,
+    /// directory hashed by the hash workload
+    pub dir: PathBuf,
+    pub julia: FractalOptions,
+    /// number of random `u64` values sorted by the sort workload
+    pub sort_len: usize,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        BenchOptions {
+            workloads: Workload::ALL.to_vec(),
+            backends: Backend::ALL.to_vec(),
+            threads: default_thread_counts(),
+            repeat: 3,
+            dir: PathBuf::from("."),
+            julia: FractalOptions {
+                width: 800,
+                height: 600,
+                ..Default::default()
+            },
+            sort_len: 2_000_000,
+        }
+    }
+}
+
+/// 1, 2, 4, ... up to and including the number of CPUs
+pub fn default_thread_counts() -> Vec<usize> {
+    let cpus = num_cpus::get();
+    let mut counts: Vec<_> = (0..).map(|p| 1 << p).take_while(|&n| n < cpus).collect();
+    counts.push(cpus);
+    counts
+}
+
+/// One row of the report. The speed-up is relative to a sequential run of
+/// the same workload, the efficiency is the speed-up per thread.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchResult {
+    pub workload: &'static str,
+    pub backend: &'static str,
+    pub threads: usize,
+    pub wall_ms: f64,
+    pub speedup: f64,
+    pub efficiency: f64,
+}
+
+/// Prepared input of a workload, shared by all the runs
+enum Input {
+    Files(Vec<PathBuf>),
+    Julia(FractalOptions),
+    Numbers(Vec<u64>),
+}
+
+impl Input {
+    fn prepare(workload: Workload, opts: &BenchOptions) -> Input {
+        match workload {
+            Workload::Hash => Input::Files(
+                WalkDir::new(&opts.dir)
+                    .into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_file())
+                    .map(|e| e.into_path())
+                    .collect(),
+            ),
+            Workload::Julia => Input::Julia(opts.julia.clone()),
+            Workload::Sort => {
+                let mut rng = StdRng::seed_from_u64(42);
+                Input::Numbers((0..opts.sort_len).map(|_| rng.gen()).collect())
+            }
+        }
+    }
+
+    /// Runs the workload, `None` runs it sequentially on the calling thread.
+    /// Returns a fingerprint of the output, so that the backends can be
+    /// checked against the sequential run.
+    fn run(&self, exec: Option<(Backend, usize)>) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match self {
+            Input::Files(files) => {
+                let digests = execute(exec, files.clone(), |path: PathBuf| {
+                    compute_digest(path, &[DigestAlgo::Sha256])
+                        .ok()
+                        .map(|(d, _)| d)
+                });
+                digests.hash(&mut hasher);
+            }
+            Input::Julia(opts) => {
+                let opts = opts.clone();
+                let rows = execute(exec, (0..opts.height).collect(), move |y| {
+                    julia_row(&opts, y)
+                });
+                rows.hash(&mut hasher);
+            }
+            Input::Numbers(numbers) => parallel_sort(exec, numbers.clone()).hash(&mut hasher),
+        }
+        hasher.finish()
+    }
+}
+
+fn execute<T, R, F>(exec: Option<(Backend, usize)>, tasks: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> R + Send + Sync + 'static,
+{
+    match exec {
+        Some((backend, threads)) => backend.run(threads, tasks, f),
+        None => tasks.into_iter().map(f).collect(),
+    }
+}
+
+fn julia_row(opts: &FractalOptions, y: u32) -> Vec<u8> {
+    (0..opts.width)
+        .flat_map(|x| {
+            colour(
+                opts.palette,
+                escape_time(opts, x, y) as f64,
+                opts.max_iterations,
+            )
+            .0
+        })
+        .collect()
+}
+
+fn parallel_sort(exec: Option<(Backend, usize)>, numbers: Vec<u64>) -> Vec<u64> {
+    let threads = exec.map_or(1, |(_, t)| t);
+    let chunk = numbers.len().div_ceil(threads).max(1);
+    let chunks: Vec<Vec<u64>> = numbers.chunks(chunk).map(|c| c.to_vec()).collect();
+    let mut runs = execute(exec, chunks, |mut c| {
+        c.sort_unstable();
+        c
+    });
+
+    while runs.len() > 1 {
+        let mut pairs = Vec::new();
+        let mut it = runs.into_iter();
+        while let Some(a) = it.next() {
+            pairs.push((a, it.next().unwrap_or_default()));
+        }
+        runs = execute(exec, pairs, |(a, b)| merge(&a, &b));
+    }
+    runs.pop().unwrap_or_default()
+}
+
+fn merge(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut out = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] <= b[j] {
+            out.push(a[i]);
+            i += 1;
+        } else {
+            out.push(b[j]);
+            j += 1;
+        }
+    }
+    out.extend_from_slice(&a[i..]);
+    out.extend_from_slice(&b[j..]);
+    out
+}
+
+fn best_of(repeat: usize, mut f: impl FnMut() -> u64) -> (Duration, u64) {
+    let mut best = Duration::MAX;
+    let mut fingerprint = 0;
+    for _ in 0..repeat.max(1) {
+        let start = Instant::now();
+        fingerprint = f();
+        best = best.min(start.elapsed());
+    }
+    (best, fingerprint)
+}
+
+/// Measures every workload sequentially and then with every backend and
+/// thread count. Panics when a backend produces a different output.
+pub fn bench_concurrency(opts: &BenchOptions) -> Vec<BenchResult> {
+    let mut results = Vec::new();
+    for &workload in &opts.workloads {
+        let input = Input::prepare(workload, opts);
+        // warms up the page cache for the hash workload
+        input.run(None);
+
+        let (sequential, expected) = best_of(opts.repeat, || input.run(None));
+        let row = |backend, threads, wall: Duration| {
+            let speedup = sequential.as_secs_f64() / wall.as_secs_f64().max(f64::EPSILON);
+            BenchResult {
+                workload: workload.name(),
+                backend,
+                threads,
+                wall_ms: wall.as_secs_f64() * 1000.0,
+                speedup,
+                efficiency: speedup / threads as f64,
+            }
+        };
+        results.push(row("sequential", 1, sequential));
+
+        for &backend in &opts.backends {
+            for &threads in &opts.threads {
+                let (wall, fingerprint) =
+                    best_of(opts.repeat, || input.run(Some((backend, threads))));
+                assert_eq!(
+                    fingerprint, expected,
+                    "{} with {} threads gave a different {} result",
+                    backend, threads, workload
+                );
+                results.push(row(backend.name(), threads, wall));
+            }
+        }
+    }
+    results
+}
+
+pub fn print_table(results: &[BenchResult]) {
+    println!(
+        "{:<8} {:<12} {:>7} {:>12} {:>9} {:>11}",
+        "workload", "backend", "threads", "wall [ms]", "speed-up", "efficiency"
+    );
+    for r in results {
+        println!(
+            "{:<8} {:<12} {:>7} {:>12.2} {:>8.2}x {:>10.0}%",
+            r.workload,
+            r.backend,
+            r.threads,
+            r.wall_ms,
+            r.speedup,
+            r.efficiency * 100.0
+        );
+    }
+}
+
+pub fn write_csv<R: Serialize>(path: &Path, results: &[R]) -> Result<(), csv::Error> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    for r in results {
+        wtr.serialize(r)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// What `bench_heap` measures: Dijkstra on a random graph, and a job queue
+/// whose priorities keep changing while jobs are taken out
+#[derive(Debug, Clone)]
+pub struct HeapBenchOptions {
+    pub nodes: usize,
+    pub edges_per_node: usize,
+    pub jobs: usize,
+    pub updates: usize,
+    pub arities: Vec<usize>,
+    pub repeat: usize,
+}
+
+impl Default for HeapBenchOptions {
+    fn default() -> Self {
+        HeapBenchOptions {
+            nodes: 200_000,
+            edges_per_node: 8,
+            jobs: 100_000,
+            updates: 1_000_000,
+            arities: vec![2, 4, 8],
+            repeat: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HeapBenchResult {
+    pub workload: &'static str,
+    pub heap: String,
+    pub wall_ms: f64,
+    /// most entries the heap held at once, stale ones included
+    pub peak_len: usize,
+    /// time of the lazy BinaryHeap divided by this one
+    pub speedup: f64,
+}
+
+// `None` is BinaryHeap with lazy deletion, `Some(d)` the indexed d-ary heap
+type HeapKind = Option<usize>;
+
+fn heap_name(kind: HeapKind) -> String {
+    match kind {
+        None => "BinaryHeap lazy".to_string(),
+        Some(d) => format!("indexed d={}", d),
+    }
+}
+
+/// Sum of the distances from node 0, and the peak heap length
+fn heap_dijkstra(kind: HeapKind, adjacency: &[Vec<(u32, u64)>]) -> (u64, usize) {
+    let mut dist = vec![u64::MAX; adjacency.len()];
+    let mut peak = 0;
+    dist[0] = 0;
+    match kind {
+        None => {
+            let mut heap = BinaryHeap::from([Reverse((0, 0u32))]);
+            while let Some(Reverse((d, n))) = heap.pop() {
+                if d > dist[n as usize] {
+                    continue; // stale, the node was reached on a shorter way
+                }
+                for &(m, w) in &adjacency[n as usize] {
+                    if d + w < dist[m as usize] {
+                        dist[m as usize] = d + w;
+                        heap.push(Reverse((d + w, m)));
+                        peak = peak.max(heap.len());
+                    }
+                }
+            }
+        }
+        Some(arity) => {
+            let mut heap = IndexedHeap::with_arity(arity);
+            let mut handles = vec![None; adjacency.len()];
+            handles[0] = Some(heap.push(0, 0u32));
+            while let Some((d, n)) = heap.pop() {
+                for &(m, w) in &adjacency[n as usize] {
+                    if d + w < dist[m as usize] {
+                        dist[m as usize] = d + w;
+                        match handles[m as usize] {
+                            Some(h) if heap.contains(h) => {
+                                heap.change_priority(h, d + w);
+                            }
+                            _ => handles[m as usize] = Some(heap.push(d + w, m)),
+                        }
+                        peak = peak.max(heap.len());
+                    }
+                }
+            }
+        }
+    }
+    let reached = dist.iter().filter(|&&d| d != u64::MAX);
+    (reached.fold(0, |sum, d| sum.wrapping_add(*d)), peak)
+}
+
+// pops until an entry which is still current
+fn next_job(
+    heap: &mut BinaryHeap<Reverse<(u64, usize)>>,
+    current: &[u64],
+    done: &mut [bool],
+) -> Option<(usize, u64)> {
+    while let Some(Reverse((p, j))) = heap.pop() {
+        if !done[j] && p == current[j] {
+            done[j] = true;
+            return Some((j, p));
+        }
+    }
+    None
+}
+
+/// Applies `updates` as (job, priority) changes, runs the most urgent job
+/// after every 8 of them and finally drains the queue. Returns a checksum of
+/// the run order and the peak heap length.
+fn heap_jobs(kind: HeapKind, priorities: &[u64], updates: &[(usize, u64)]) -> (u64, usize) {
+    let mut checksum = 0u64;
+    let mut run = |job: usize, p: u64| {
+        checksum = checksum.wrapping_mul(31).wrapping_add(p ^ job as u64);
+    };
+    let mut peak = priorities.len();
+    match kind {
+        None => {
+            let mut current = priorities.to_vec();
+            let mut done = vec![false; priorities.len()];
+            let mut heap: BinaryHeap<_> = priorities
+                .iter()
+                .enumerate()
+                .map(|(j, &p)| Reverse((p, j)))
+                .collect();
+            for (i, &(j, p)) in updates.iter().enumerate() {
+                if !done[j] {
+                    current[j] = p;
+                    heap.push(Reverse((p, j)));
+                    peak = peak.max(heap.len());
+                }
+                if i % 8 == 7 {
+                    if let Some((j, p)) = next_job(&mut heap, &current, &mut done) {
+                        run(j, p);
+                    }
+                }
+            }
+            while let Some((j, p)) = next_job(&mut heap, &current, &mut done) {
+                run(j, p);
+            }
+        }
+        Some(arity) => {
+            let mut heap = IndexedHeap::with_arity(arity);
+            let handles: Vec<_> = priorities
+                .iter()
+                .enumerate()
+                .map(|(j, &p)| heap.push(p, j))
+                .collect();
+            for (i, &(j, p)) in updates.iter().enumerate() {
+                // a job which already ran has a dead handle, nothing happens
+                heap.change_priority(handles[j], p);
+                if i % 8 == 7 {
+                    if let Some((p, j)) = heap.pop() {
+                        run(j, p);
+                    }
+                }
+            }
+            while let Some((p, j)) = heap.pop() {
+                run(j, p);
+            }
+        }
+    }
+    (checksum, peak)
+}
+
+/// Runs both workloads with a lazy BinaryHeap and an indexed heap of every
+/// arity. Panics when a heap produces a different result.
+pub fn bench_heap(opts: &HeapBenchOptions) -> Vec<HeapBenchResult> {
+    let mut rng = StdRng::seed_from_u64(7);
+    let nodes = opts.nodes.max(1);
+    let adjacency: Vec<Vec<(u32, u64)>> = (0..nodes)
+        .map(|_| {
+            (0..opts.edges_per_node)
+                .map(|_| (rng.gen_range(0..nodes) as u32, rng.gen_range(1..=1000)))
+                .collect()
+        })
+        .collect();
+    // 64 random bits make ties, which the heaps may break differently, unlikely
+    let jobs = opts.jobs.max(1);
+    let priorities: Vec<u64> = (0..jobs).map(|_| rng.gen()).collect();
+    let updates: Vec<(usize, u64)> = (0..opts.updates)
+        .map(|_| (rng.gen_range(0..jobs), rng.gen()))
+        .collect();
+
+    let kinds: Vec<HeapKind> = std::iter::once(None)
+        .chain(opts.arities.iter().map(|&d| Some(d)))
+        .collect();
+    let mut results = Vec::new();
+    for workload in ["dijkstra", "jobs"] {
+        let mut expected = None;
+        let mut lazy_ms = 0.0;
+        for &kind in &kinds {
+            let mut peak_len = 0;
+            let (wall, fingerprint) = best_of(opts.repeat, || {
+                let (fingerprint, peak) = if workload == "dijkstra" {
+                    heap_dijkstra(kind, &adjacency)
+                } else {
+                    heap_jobs(kind, &priorities, &updates)
+                };
+                peak_len = peak;
+                fingerprint
+            });
+            assert_eq!(
+                fingerprint,
+                *expected.get_or_insert(fingerprint),
+                "{} gave a different result for {}",
+                heap_name(kind),
+                workload
+            );
+            let wall_ms = wall.as_secs_f64() * 1000.0;
+            if kind.is_none() {
+                lazy_ms = wall_ms;
+            }
+            results.push(HeapBenchResult {
+                workload,
+                heap: heap_name(kind),
+                wall_ms,
+                peak_len,
+                speedup: lazy_ms / wall_ms,
+            });
+        }
+    }
+    results
+}
+
+pub fn print_heap_table(results: &[HeapBenchResult]) {
+    println!(
+        "{:<8} {:<16} {:>12} {:>10} {:>9}",
+        "workload", "heap", "wall [ms]", "peak len", "speed-up"
+    );
+    for r in results {
+        println!(
+            "{:<8} {:<16} {:>12.2} {:>10} {:>8.2}x",
+            r.workload, r.heap, r.wall_ms, r.peak_len, r.speedup
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backends_keep_task_order() {
+        let tasks: Vec<u64> = (0..100).collect();
+        let expected: Vec<u64> = tasks.iter().map(|x| x * x).collect();
+        for backend in Backend::ALL {
+            for threads in [1, 3, 8, 200] {
+                assert_eq!(backend.run(threads, tasks.clone(), |x| x * x), expected);
+            }
+            assert!(backend.run(4, Vec::<u64>::new(), |x| x).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_bench_concurrency_small() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..5 {
+            std::fs::write(dir.path().join(format!("{}.txt", i)), vec![i as u8; 1000]).unwrap();
+        }
+        let opts = BenchOptions {
+            threads: vec![1, 3],
+            repeat: 1,
+            dir: dir.path().to_owned(),
+            julia: FractalOptions {
+                width: 40,
+                height: 30,
+                ..Default::default()
+            },
+            sort_len: 1001,
+            ..Default::default()
+        };
+
+        let results = bench_concurrency(&opts);
+        // one sequential row plus 4 backends * 2 thread counts for each workload
+        assert_eq!(results.len(), 3 * (1 + 4 * 2));
+        assert!(results.iter().all(|r| r.speedup > 0.0));
+
+        let csv = dir.path().join("bench.csv");
+        write_csv(&csv, &results).unwrap();
+        let text = std::fs::read_to_string(csv).unwrap();
+        assert!(text.starts_with("workload,backend,threads,wall_ms,speedup,efficiency\n"));
+        assert_eq!(text.lines().count(), results.len() + 1);
+    }
+
+    #[test]
+    fn test_bench_heap_small() {
+        let opts = HeapBenchOptions {
+            nodes: 500,
+            edges_per_node: 3,
+            jobs: 300,
+            updates: 2000,
+            arities: vec![2, 5],
+            repeat: 1,
+        };
+        // bench_heap itself checks that all the heaps agree
+        let results = bench_heap(&opts);
+        assert_eq!(results.len(), 2 * 3);
+        assert_eq!(results[0].speedup, 1.0);
+        // the lazy heap keeps stale entries, the indexed one never holds more than the jobs
+        assert!(results[3].peak_len > 300);
+        assert!(results[4..].iter().all(|r| r.peak_len == 300));
+    }
+
+    #[test]
+    fn test_parallel_sort() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let numbers: Vec<u64> = (0..777).map(|_| rng.gen_range(0..50)).collect();
+        let mut expected = numbers.clone();
+        expected.sort();
+        for threads in [1, 2, 5] {
+            let sorted = parallel_sort(Some((Backend::Crossbeam, threads)), numbers.clone());
+            assert_eq!(sorted, expected);
+        }
+        assert_eq!(parallel_sort(None, numbers), expected);
+    }
+}
@@ -0,0 +1,124 @@
+// Cooperative cancellation for long running pool jobs. Workers check the token
+// before they pick up the next item, so a cancelled run stops taking new work,
+// lets the items in flight finish and can still report what it got done.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const RUNNING: u8 = 0;
+const INTERRUPTED: u8 = 1;
+const TIMED_OUT: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// `cancel` was called, e.g. by the Ctrl-C handler
+    Interrupted,
+    /// the deadline passed
+    TimedOut,
+}
+
+impl CancelReason {
+    /// Exit status of a cancelled command, the same as a shell and `timeout(1)` use
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CancelReason::Interrupted => 130,
+            CancelReason::TimedOut => 124,
+        }
+    }
+}
+
+impl fmt::Display for CancelReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CancelReason::Interrupted => write!(f, "interrupted"),
+            CancelReason::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// Cheap to clone, all the clones share the same state
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<AtomicU8>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token which cancels itself once `timeout` has elapsed
+    pub fn with_timeout(timeout: Duration) -> Self {
+        CancellationToken {
+            state: Arc::default(),
+            deadline: Instant::now().checked_add(timeout),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.set(INTERRUPTED);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.reason().is_some()
+    }
+
+    /// Why the token was cancelled, the first reason wins
+    pub fn reason(&self) -> Option<CancelReason> {
+        if self.deadline.is_some_and(|d| Instant::now() >= d) {
+            self.set(TIMED_OUT);
+        }
+        match self.state.load(Ordering::Acquire) {
+            INTERRUPTED => Some(CancelReason::Interrupted),
+            TIMED_OUT => Some(CancelReason::TimedOut),
+            _ => None,
+        }
+    }
+
+    /// Cancels the token on SIGINT, a second Ctrl-C exits right away.
+    /// Only one handler can be installed per process.
+    pub fn cancel_on_ctrl_c(&self) -> Result<(), ctrlc::Error> {
+        let token = self.clone();
+        ctrlc::set_handler(move || {
+            if token.is_cancelled() {
+                std::process::exit(CancelReason::Interrupted.exit_code());
+            }
+            eprintln!("Interrupted, finishing the items in progress (press Ctrl-C again to quit)");
+            token.cancel();
+        })
+    }
+
+    fn set(&self, reason: u8) {
+        let _ = self
+            .state
+            .compare_exchange(RUNNING, reason, Ordering::AcqRel, Ordering::Acquire);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_is_shared_by_clones() {
+        let token = CancellationToken::new();
+        let worker = token.clone();
+        assert!(!worker.is_cancelled());
+        token.cancel();
+        assert_eq!(worker.reason(), Some(CancelReason::Interrupted));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let token = CancellationToken::with_timeout(Duration::from_millis(20));
+        assert!(!token.is_cancelled());
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(token.reason(), Some(CancelReason::TimedOut));
+        // the first reason sticks
+        token.cancel();
+        assert_eq!(token.reason(), Some(CancelReason::TimedOut));
+    }
+}
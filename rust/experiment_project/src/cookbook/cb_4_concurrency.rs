@@ -6,6 +6,8 @@ pub fn main() {
     println!("Concurrency demo");
     short_lived_threads();
     println!("------------------------");
+    parallel_algorithms();
+    println!("------------------------");
     parallel_pipeline();
     println!("------------------------");
     pass_data_two_threads();
@@ -26,69 +28,42 @@ fn short_lived_threads() {
 }
 
 fn find_max(arr: &[i32]) -> Option<i32> {
-    const THRESHOLD: usize = 2;
-    if arr.len() <= THRESHOLD {
-        return arr.iter().copied().max();
-    }
+    use super::cb_4_par_algorithms::{map_reduce, Cutoff};
 
-    let mid = arr.len() / 2;
-    let (left, right) = arr.split_at(mid);
-    crossbeam::scope(|s| {
-        let thread_l = s.spawn(|_| find_max(left));
-        let thread_r = s.spawn(|_| find_max(right));
+    map_reduce(arr, |x| Some(*x), None, Option::max, Cutoff::Auto)
+}
 
-        let max_l = thread_l.join().unwrap();
-        let max_r = thread_r.join().unwrap();
+fn parallel_algorithms() {
+    use super::cb_4_par_algorithms::{merge_sort, prefix_sum, quickselect, reduce, Cutoff};
 
-        Some(max_l.max(max_r))
-    })
-    .unwrap()
-    .unwrap()
+    let mut arr = [9, 3, 7, 1, 8, 2, 6, 4, 5];
+    let cutoff = Cutoff::Fixed(2);
+    println!("Sum: {}", reduce(&arr, 0, |a, b| a + b, cutoff));
+    println!("Median: {:?}", quickselect(&arr, arr.len() / 2, cutoff));
+    println!("Running totals: {:?}", prefix_sum(&arr, cutoff));
+    merge_sort(&mut arr, cutoff);
+    println!("Sorted: {:?}", arr);
 }
 
 fn parallel_pipeline() {
-    use crossbeam_channel::bounded;
+    use super::cb_4_pipeline::Pipeline;
     use std::thread;
-    use std::time::Duration;
-
-    let (snd1, rcv1) = bounded(1);
-    let (snd2, rcv2) = bounded(2);
 
-    let n_msgs = 4;
-    let n_workers = 2;
-
-    crossbeam::scope(|s| {
-        s.spawn(|_| {
-            for i in 0..n_msgs {
-                snd1.send(i).unwrap();
-                println!("Source send {}", i);
-            }
-            // close the channel
-            // necessary to exit for-loop in the worker
-            drop(snd1);
+    let res = Pipeline::source(0..4)
+        .preserve_order(true)
+        .stage("double", 2, 1, |msg: i32| {
+            println!("Worker {:?} received {}.", thread::current().id(), msg);
+            Ok::<_, String>(msg * 2)
+        })
+        .sink(|msg| {
+            println!("Sink received {}", msg);
+            Ok(())
         });
 
-        // processing
-        for _ in 0..n_workers {
-            let (sendr, recvr) = (snd2.clone(), rcv1.clone());
-
-            s.spawn(move |_| {
-                thread::sleep(Duration::from_millis(500));
-                for msg in recvr.iter() {
-                    println!("Worker {:?} received {}.", thread::current().id(), msg);
-                    sendr.send(msg * 2).unwrap();
-                }
-            });
-        }
-
-        drop(snd2);
-
-        // sink
-        for msg in rcv2.iter() {
-            println!("Sink received {}", msg);
-        }
-    })
-    .unwrap();
+    match res {
+        Ok(report) => print!("{}", report),
+        Err(e) => println!("Pipeline failed: {}", e),
+    }
 }
 
 fn pass_data_two_threads() {
@@ -115,124 +90,848 @@ fn pass_data_two_threads() {
 }
 
 fn global_mutable_state() {
+    use super::cb_4_kv_store::ShardedStore;
     use lazy_static::lazy_static;
-    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
 
     lazy_static! {
-        static ref FRUIT: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        static ref FRUIT: ShardedStore<String, u32> = ShardedStore::new();
     }
 
+    // adds one more fruit, retrying when another thread got there first
     fn insert(fruit: &str) {
-        let mut db = FRUIT
-            .lock()
-            .map_err(|_| "Failed to acquire MutexGuard")
-            .unwrap();
-        db.push(fruit.to_string());
+        loop {
+            let current = FRUIT.get(&fruit.to_string());
+            let next = current.unwrap_or(0) + 1;
+            if FRUIT
+                .compare_and_swap(fruit.to_string(), current.as_ref(), Some(next))
+                .is_ok()
+            {
+                break;
+            }
+        }
     }
 
-    insert("apple");
-    insert("orange");
-    insert("peach");
+    let handles: Vec<_> = ["apple", "orange", "peach", "apple"]
+        .into_iter()
+        .map(|fruit| thread::spawn(move || insert(fruit)))
+        .collect();
+    handles.into_iter().for_each(|h| h.join().unwrap());
+
+    let mut fruit = FRUIT.snapshot();
+    fruit.sort();
+    fruit
+        .iter()
+        .for_each(|(name, count)| println!("{}: {}", name, count));
 
-    {
-        let db = FRUIT
-            .lock()
-            .map_err(|_| "Failed to acquire MutexGuard")
-            .unwrap();
-        db.iter()
-            .enumerate()
-            .for_each(|(i, item)| println!("{}: {}", i, item));
-    }
     insert("grape");
+    FRUIT.put("melon".to_string(), 10);
+    FRUIT.put_with_ttl("cherry".to_string(), 5, Duration::from_millis(50));
+    println!("Removed {:?} oranges", FRUIT.remove(&"orange".to_string()));
+    println!(
+        "{} kinds of fruit, cherries: {:?}",
+        FRUIT.len(),
+        FRUIT.get(&"cherry".to_string())
+    );
+    thread::sleep(Duration::from_millis(60));
+    println!(
+        "Cherries expired: {:?}, purged {} entries, empty: {}",
+        FRUIT.get(&"cherry".to_string()),
+        FRUIT.purge_expired(),
+        FRUIT.is_empty()
+    );
 }
 
-use ring::digest::{Context, Digest, SHA256};
+use super::cb_4_cancel::{CancelReason, CancellationToken};
+use super::cb_7_database::hash_cache::{FileStamp, HashCache};
+use data_encoding::HEXLOWER;
+use ring::digest::{Context, SHA1_FOR_LEGACY_USE_ONLY, SHA256, SHA384, SHA512};
+use std::fmt;
 use std::fs::File;
-use std::io::{BufReader, Error, Read};
+use std::io::{BufRead, BufReader, Error, Read};
+use std::str::FromStr;
 use threadpool::ThreadPool;
 use walkdir::WalkDir;
 
-pub fn sha256_files(path: &PathBuf) {
-    println!("Calculating sha256 for {:?} directory...", path);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgo {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+    /// 64-bit FNV-1a, fast but not cryptographic - good enough for dedup
+    Fnv1a64,
+}
+
+impl DigestAlgo {
+    pub const ALL: [DigestAlgo; 5] = [
+        DigestAlgo::Sha1,
+        DigestAlgo::Sha256,
+        DigestAlgo::Sha384,
+        DigestAlgo::Sha512,
+        DigestAlgo::Fnv1a64,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DigestAlgo::Sha1 => "sha1",
+            DigestAlgo::Sha256 => "sha256",
+            DigestAlgo::Sha384 => "sha384",
+            DigestAlgo::Sha512 => "sha512",
+            DigestAlgo::Fnv1a64 => "fnv1a64",
+        }
+    }
+
+    // tag used by the BSD style `ALGO (file) = hex` lines
+    fn tag(&self) -> String {
+        self.name().to_uppercase()
+    }
+
+    fn output_len(&self) -> usize {
+        match self {
+            DigestAlgo::Sha1 => SHA1_FOR_LEGACY_USE_ONLY.output_len,
+            DigestAlgo::Sha256 => SHA256.output_len,
+            DigestAlgo::Sha384 => SHA384.output_len,
+            DigestAlgo::Sha512 => SHA512.output_len,
+            DigestAlgo::Fnv1a64 => 8,
+        }
+    }
+}
+
+impl fmt::Display for DigestAlgo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for DigestAlgo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DigestAlgo::ALL
+            .iter()
+            .find(|a| a.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown digest algorithm '{}'", s))
+    }
+}
+
+enum Hasher {
+    Ring(Box<Context>),
+    Fnv1a64(u64),
+}
+
+impl Hasher {
+    fn new(algo: DigestAlgo) -> Hasher {
+        match algo {
+            DigestAlgo::Sha1 => Hasher::Ring(Box::new(Context::new(&SHA1_FOR_LEGACY_USE_ONLY))),
+            DigestAlgo::Sha256 => Hasher::Ring(Box::new(Context::new(&SHA256))),
+            DigestAlgo::Sha384 => Hasher::Ring(Box::new(Context::new(&SHA384))),
+            DigestAlgo::Sha512 => Hasher::Ring(Box::new(Context::new(&SHA512))),
+            DigestAlgo::Fnv1a64 => Hasher::Fnv1a64(0xcbf2_9ce4_8422_2325),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Ring(ctx) => ctx.update(data),
+            Hasher::Fnv1a64(h) => {
+                for b in data {
+                    *h ^= *b as u64;
+                    *h = h.wrapping_mul(0x0000_0100_0000_01b3);
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            Hasher::Ring(ctx) => ctx.finish().as_ref().to_vec(),
+            Hasher::Fnv1a64(h) => h.to_be_bytes().to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HashSummary {
+    /// files with a digest, hashed or taken from the cache
+    pub hashed: usize,
+    pub cache_hits: usize,
+    pub failed: usize,
+    /// files left alone because the run was cancelled
+    pub skipped: usize,
+    pub cancelled: Option<CancelReason>,
+}
+
+/// Prints `<hex>  <path>` lines sorted by path, the same way `sha256sum` does.
+/// When more than one algorithm is requested every file is read once and
+/// the digests are printed as `ALGO (path) = hex` lines, like `sha256sum --tag`.
+/// With a cache only files whose size, mtime or inode changed are rehashed,
+/// and the cache database itself is left out when it sits inside `path`.
+/// Once `cancel` fires no new files are hashed, the digests computed so far
+/// are still printed and cached.
+pub fn sha256_files(
+    path: &PathBuf,
+    algos: &[DigestAlgo],
+    mut cache: Option<&mut HashCache>,
+    cancel: &CancellationToken,
+) -> HashSummary {
     let pool = ThreadPool::new(num_cpus::get());
 
     let (tx, rx) = channel();
+    let mut results = Vec::new();
+    let mut scan_stopped = false;
 
     for entry in WalkDir::new(path)
         .follow_links(true)
         .into_iter()
         .filter_map(|e| e.ok())
         .filter(|e| !e.path().is_dir())
+        .filter(|e| !cache.as_deref().is_some_and(|c| c.is_own_file(e.path())))
     {
+        if cancel.is_cancelled() {
+            scan_stopped = true;
+            break;
+        }
         let path = entry.path().to_owned();
+        let stamp = entry.metadata().ok().map(|m| FileStamp::from(&m));
+
+        if let (Some(cache), Some(stamp)) = (cache.as_deref(), stamp) {
+            let cached: Option<Vec<_>> = algos
+                .iter()
+                .map(|a| cache.get(&path, a.name(), &stamp).ok().flatten())
+                .collect();
+            if let Some(digests) = cached {
+                results.push((path, Ok(digests)));
+                continue;
+            }
+        }
+
+        let algos = algos.to_vec();
         let tx = tx.clone();
+        let cancel = cancel.clone();
         pool.execute(move || {
-            let digest = compute_digest(path);
-            tx.send(digest).expect("Could not send data");
+            // jobs still queued when the run is cancelled report `None`
+            let digests = (!cancel.is_cancelled())
+                .then(|| compute_digest(path.clone(), &algos).map(|(d, _)| d));
+            tx.send((path, stamp, digests))
+                .expect("Could not send data");
         });
     }
 
     drop(tx);
-    for t in rx.iter() {
-        let (sha, path) = t.unwrap();
-        println!("{:?} {:?}", sha, path);
+    let mut summary = HashSummary {
+        cache_hits: results.len(),
+        ..Default::default()
+    };
+    let fresh: Vec<_> = rx
+        .iter()
+        .filter_map(|(path, stamp, digests)| {
+            if digests.is_none() {
+                summary.skipped += 1;
+            }
+            Some((path, stamp, digests?))
+        })
+        .collect();
+
+    if let Some(cache) = cache.as_deref_mut() {
+        let entries = fresh.iter().filter_map(|(path, stamp, digests)| {
+            let digests = digests.as_ref().ok()?;
+            let names = algos.iter().map(|a| a.name());
+            let stored = names.zip(digests.iter().map(|d| d.as_slice())).collect();
+            Some((path.as_path(), (*stamp)?, stored))
+        });
+        if let Err(e) = cache.put_all(entries) {
+            eprintln!("Could not update the hash cache: {}", e);
+        }
+    }
+
+    results.extend(fresh.into_iter().map(|(path, _, digests)| (path, digests)));
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let tagged = algos.len() > 1;
+    let n_files = results.len();
+    for (path, digests) in results {
+        match digests {
+            Ok(digests) => {
+                summary.hashed += 1;
+                for (algo, digest) in algos.iter().zip(digests) {
+                    let tag = if tagged { Some(*algo) } else { None };
+                    println!("{}", sha256sum_line(&digest, &path, tag));
+                }
+            }
+            Err(e) => {
+                summary.failed += 1;
+                eprintln!("{}sum: {}: {}", algos[0], path.display(), e);
+            }
+        }
+    }
+
+    // stderr, so that stdout stays a valid checksum list
+    if cache.is_some() {
+        eprintln!("Cache hits: {} of {} files", summary.cache_hits, n_files);
+    }
+    summary.cancelled = cancel.reason();
+    if let Some(reason) = summary.cancelled {
+        eprintln!(
+            "Hashing {}: {} files hashed, {} failed, {} skipped{}",
+            reason,
+            summary.hashed,
+            summary.failed,
+            summary.skipped,
+            if scan_stopped {
+                ", directory scan stopped early"
+            } else {
+                ""
+            }
+        );
     }
+    summary
+}
+
+/// Verifies a `sha256sum` checksum list, returns `true` when every file matched.
+/// Untagged lines are checked with `algo`, tagged lines with the algorithm they name.
+pub fn sha256_check(list: &Path, algo: DigestAlgo) -> bool {
+    let tool = format!("{}sum", algo);
+    let file = match File::open(list) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}: {}: {}", tool, list.display(), e);
+            return false;
+        }
+    };
+
+    let mut expected = Vec::new();
+    let mut n_improper = 0;
+    for line in BufReader::new(file).lines() {
+        match line
+            .ok()
+            .as_deref()
+            .and_then(|l| parse_sha256sum_line(l, algo))
+        {
+            Some(entry) => expected.push(entry),
+            None => n_improper += 1,
+        }
+    }
+
+    let pool = ThreadPool::new(num_cpus::get());
+    let (tx, rx) = channel();
+
+    for (idx, (algo, _, path)) in expected.iter().enumerate() {
+        let (algo, path) = (*algo, path.clone());
+        let tx = tx.clone();
+        pool.execute(move || {
+            let digest = compute_digest(path, &[algo]);
+            tx.send((idx, digest)).expect("Could not send data");
+        });
+    }
+
+    drop(tx);
+    let mut results: Vec<_> = rx.iter().collect();
+    results.sort_by_key(|(idx, _)| *idx);
+
+    let (mut n_failed, mut n_unreadable) = (0, 0);
+    for ((_, hex, path), (_, digest)) in expected.iter().zip(results) {
+        match digest {
+            Ok((digests, _)) if HEXLOWER.encode(&digests[0]) == *hex => {
+                println!("{}: OK", path.display())
+            }
+            Ok(_) => {
+                n_failed += 1;
+                println!("{}: FAILED", path.display());
+            }
+            Err(e) => {
+                n_unreadable += 1;
+                eprintln!("{}: {}: {}", tool, path.display(), e);
+                println!("{}: FAILED open or read", path.display());
+            }
+        }
+    }
+
+    if n_improper > 0 {
+        eprintln!(
+            "{}: WARNING: {} line{} improperly formatted",
+            tool,
+            n_improper,
+            if n_improper == 1 { " is" } else { "s are" }
+        );
+    }
+    if n_unreadable > 0 {
+        eprintln!(
+            "{}: WARNING: {} listed file{} could not be read",
+            tool,
+            n_unreadable,
+            if n_unreadable == 1 { "" } else { "s" }
+        );
+    }
+    if n_failed > 0 {
+        eprintln!(
+            "{}: WARNING: {} computed checksum{} did NOT match",
+            tool,
+            n_failed,
+            if n_failed == 1 { "" } else { "s" }
+        );
+    }
+
+    !expected.is_empty() && n_failed == 0 && n_unreadable == 0
+}
+
+// sha256sum escapes '\\' and '\n' in file names and marks such lines with a leading '\\'
+fn sha256sum_line(digest: &[u8], path: &Path, tag: Option<DigestAlgo>) -> String {
+    let name = path.to_string_lossy();
+    let hex = HEXLOWER.encode(digest);
+    let escaped = name.contains('\\') || name.contains('\n');
+    let name = if escaped {
+        name.replace('\\', "\\\\").replace('\n', "\\n")
+    } else {
+        name.to_string()
+    };
+    let line = match tag {
+        Some(algo) => format!("{} ({}) = {}", algo.tag(), name, hex),
+        None => format!("{}  {}", hex, name),
+    };
+    if escaped {
+        format!("\\{}", line)
+    } else {
+        line
+    }
+}
+
+fn parse_sha256sum_line(line: &str, algo: DigestAlgo) -> Option<(DigestAlgo, String, PathBuf)> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+
+    let tagged = DigestAlgo::ALL.iter().find_map(|a| {
+        let rest = line.strip_prefix(&a.tag())?.strip_prefix(" (")?;
+        let (name, hex) = rest.rsplit_once(") = ")?;
+        Some((*a, hex, name))
+    });
+    let (algo, hex, name) = match tagged {
+        Some(t) => t,
+        None => {
+            let (hex, rest) = line.split_at(line.find(' ')?);
+            // text mode uses two spaces, binary mode a space and an asterisk
+            let name = rest.strip_prefix("  ").or(rest.strip_prefix(" *"))?;
+            (algo, hex, name)
+        }
+    };
+    if hex.len() != 2 * algo.output_len()
+        || !hex.chars().all(|c| c.is_ascii_hexdigit())
+        || name.is_empty()
+    {
+        return None;
+    }
+
+    let name = if escaped {
+        let mut out = String::new();
+        let mut chars = name.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            match chars.next()? {
+                'n' => out.push('\n'),
+                '\\' => out.push('\\'),
+                _ => return None,
+            }
+        }
+        out
+    } else {
+        name.to_string()
+    };
+
+    Some((algo, hex.to_ascii_lowercase(), PathBuf::from(name)))
 }
 
 fn compute_julia() {
-    use image::{ImageBuffer, Rgb};
+    use fractal::{render, FractalOptions};
+
+    let _ = render(&FractalOptions::default()).save("output.png");
+}
+
+/// Julia and Mandelbrot sets rendered in parallel over bands of rows
+pub mod fractal {
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::{Delay, DynamicImage, Frame, ImageBuffer, ImageResult, Rgb, RgbImage};
     use num::complex::Complex;
+    use rayon::prelude::*;
+    use std::fs::File;
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+
+    const ESCAPE_RADIUS: f64 = 3.0;
+    // a large bailout keeps the normalised iteration count continuous
+    const SMOOTH_ESCAPE_RADIUS: f64 = 256.0;
+    const BAND_ROWS: usize = 16;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Mode {
+        Julia,
+        Mandelbrot,
+    }
+
+    impl FromStr for Mode {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "julia" => Ok(Mode::Julia),
+                "mandelbrot" => Ok(Mode::Mandelbrot),
+                _ => Err(format!("unknown fractal mode '{}'", s)),
+            }
+        }
+    }
 
-    let (width, height) = (1920, 1080);
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Palette {
+        /// shades of red, the original look of the cookbook example
+        Red,
+        Grayscale,
+        /// visible spectrum from 380 nm to 780 nm
+        Wavelength,
+        Fire,
+    }
 
-    fn julia(c: Complex<f32>, x: u32, y: u32, w: u32, h: u32, max_iteration: u32) -> u32 {
-        const R: f32 = 3.0;
-        let mut zy = (y as f32 / (h as f32)) * R - R / 2.0;
-        let mut zx = (x as f32 / (w as f32)) * R - R / 2.0;
+    impl FromStr for Palette {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "red" => Ok(Palette::Red),
+                "grayscale" => Ok(Palette::Grayscale),
+                "wavelength" => Ok(Palette::Wavelength),
+                "fire" => Ok(Palette::Fire),
+                _ => Err(format!("unknown palette '{}'", s)),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct FractalOptions {
+        pub width: u32,
+        pub height: u32,
+        pub max_iterations: u32,
+        /// the constant of the Julia set, ignored for Mandelbrot
+        pub c: Complex<f64>,
+        pub center: Complex<f64>,
+        /// 1.0 shows a viewport 3.0 units high
+        pub zoom: f64,
+        pub mode: Mode,
+        pub palette: Palette,
+        /// colour by the normalised iteration count instead of the integer one
+        pub smooth: bool,
+    }
+
+    impl Default for FractalOptions {
+        fn default() -> Self {
+            FractalOptions {
+                width: 1920,
+                height: 1080,
+                max_iterations: 300,
+                c: Complex::new(-0.8, 0.156),
+                center: Complex::new(0.0, 0.0),
+                zoom: 1.0,
+                mode: Mode::Julia,
+                palette: Palette::Red,
+                smooth: false,
+            }
+        }
+    }
+
+    impl FractalOptions {
+        /// Maps a pixel to its point on the complex plane
+        pub fn point(&self, x: u32, y: u32) -> Complex<f64> {
+            let scale = ESCAPE_RADIUS / (self.zoom * self.height as f64);
+            Complex::new(
+                self.center.re + (x as f64 - self.width as f64 / 2.0) * scale,
+                self.center.im + (y as f64 - self.height as f64 / 2.0) * scale,
+            )
+        }
+    }
+
+    /// Number of iterations before the orbit of the pixel escapes
+    pub fn escape_time(opts: &FractalOptions, x: u32, y: u32) -> u32 {
+        iterate(opts, x, y, ESCAPE_RADIUS).0
+    }
+
+    /// Normalised iteration count `i + 1 - log2(ln |z|)`, continuous across
+    /// the iteration bands, so zoomed frames don't flicker
+    pub fn smooth_escape_time(opts: &FractalOptions, x: u32, y: u32) -> f64 {
+        let (i, z) = iterate(opts, x, y, SMOOTH_ESCAPE_RADIUS);
+        if i >= opts.max_iterations {
+            return i as f64;
+        }
+        let nu = (z.norm().ln()).ln() / std::f64::consts::LN_2;
+        (i as f64 + 1.0 - nu).clamp(0.0, opts.max_iterations as f64)
+    }
+
+    fn iterate(opts: &FractalOptions, x: u32, y: u32, radius: f64) -> (u32, Complex<f64>) {
+        let p = opts.point(x, y);
+        let (mut z, c) = match opts.mode {
+            Mode::Julia => (p, opts.c),
+            Mode::Mandelbrot => (Complex::new(0.0, 0.0), p),
+        };
 
         let mut iteration = 0;
-        while zx * zx + zy * zy < R * R && iteration < max_iteration {
-            let xtemp = zx * zx - zy * zy;
-            zy = 2.0 * zx * zy + c.im;
-            zx = xtemp + c.re;
+        while z.norm_sqr() < radius * radius && iteration < opts.max_iterations {
+            z = z * z + c;
             iteration += 1;
         }
-        iteration
+        (iteration, z)
     }
 
-    let mut img = ImageBuffer::new(width, height);
-    let iterations = 300;
+    pub fn render(opts: &FractalOptions) -> RgbImage {
+        let row_len = opts.width as usize * 3;
+        let mut buf = vec![0u8; row_len * opts.height as usize];
 
-    let c = Complex::new(-0.8, 0.156);
+        buf.par_chunks_mut(row_len * BAND_ROWS)
+            .enumerate()
+            .for_each(|(band, rows)| {
+                for (i, row) in rows.chunks_mut(row_len).enumerate() {
+                    let y = (band * BAND_ROWS + i) as u32;
+                    for (x, px) in row.chunks_mut(3).enumerate() {
+                        let i = if opts.smooth {
+                            smooth_escape_time(opts, x as u32, y)
+                        } else {
+                            escape_time(opts, x as u32, y) as f64
+                        };
+                        px.copy_from_slice(&colour(opts.palette, i, opts.max_iterations).0);
+                    }
+                }
+            });
 
-    let pool = ThreadPool::new(num_cpus::get());
-    let (tx, rx) = channel();
+        ImageBuffer::from_raw(opts.width, opts.height, buf).unwrap()
+    }
 
-    for y in 0..height {
-        let tx = tx.clone();
-        pool.execute(move || {
-            for x in 0..width {
-                let i = julia(c, x, y, width, height, iterations);
-                // let pixel = wavelength_to_rgb(380 + i * 400 / iterations);
-                let r = iterations - i;
-                let r = r as f32 / iterations as f32;
-                let r = 255.0 - r * 255.0;
-                let pixel = Rgb([r as u8, 0, 0]);
-                tx.send((x, y, pixel)).expect("Could not send data!");
+    /// Zoom from the `start` viewport towards `target` over `frames` frames
+    #[derive(Debug, Clone)]
+    pub struct ZoomAnimation {
+        pub start: FractalOptions,
+        pub target: Complex<f64>,
+        pub end_zoom: f64,
+        pub frames: u32,
+    }
+
+    impl ZoomAnimation {
+        /// Viewports of all frames, the zoom grows geometrically so the
+        /// apparent speed is constant and `target` keeps its screen position
+        pub fn viewports(&self) -> Vec<FractalOptions> {
+            let start_zoom = self.start.zoom;
+            let steps = self.frames.saturating_sub(1).max(1) as f64;
+            (0..self.frames)
+                .map(|k| {
+                    let zoom = start_zoom * (self.end_zoom / start_zoom).powf(k as f64 / steps);
+                    let center =
+                        self.target + (self.start.center - self.target) * (start_zoom / zoom);
+                    FractalOptions {
+                        zoom,
+                        center,
+                        ..self.start.clone()
+                    }
+                })
+                .collect()
+        }
+    }
+
+    /// Renders all frames in parallel. A `.gif` output is written as an
+    /// animated GIF, anything else as a numbered PNG sequence next to it,
+    /// e.g. `zoom.png` becomes `zoom_0000.png`, `zoom_0001.png`, ...
+    pub fn render_zoom(
+        anim: &ZoomAnimation,
+        output: &Path,
+        frame_delay_ms: u32,
+    ) -> ImageResult<()> {
+        let viewports = anim.viewports();
+        let is_gif = output
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("gif"));
+
+        if !is_gif {
+            return viewports
+                .par_iter()
+                .enumerate()
+                .try_for_each(|(k, opts)| render(opts).save(frame_path(output, k)));
+        }
+
+        let frames: Vec<_> = viewports
+            .par_iter()
+            .map(|opts| {
+                let rgba = DynamicImage::ImageRgb8(render(opts)).into_rgba8();
+                Frame::from_parts(rgba, 0, 0, Delay::from_numer_denom_ms(frame_delay_ms, 1))
+            })
+            .collect();
+
+        let mut encoder = GifEncoder::new_with_speed(File::create(output)?, 10);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames)
+    }
+
+    fn frame_path(output: &Path, k: usize) -> PathBuf {
+        let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = output
+            .extension()
+            .unwrap_or("png".as_ref())
+            .to_string_lossy();
+        output.with_file_name(format!("{}_{:04}.{}", stem, k, ext))
+    }
+
+    /// Colour of a pixel which escaped after `i` out of `max` iterations
+    pub fn colour(palette: Palette, i: f64, max: u32) -> Rgb<u8> {
+        let t = i / max as f64;
+        match palette {
+            Palette::Red => Rgb([(t * 255.0) as u8, 0, 0]),
+            Palette::Grayscale => {
+                let v = (t.sqrt() * 255.0) as u8;
+                Rgb([v, v, v])
             }
-        });
+            Palette::Wavelength if t >= 1.0 => Rgb([0, 0, 0]),
+            Palette::Wavelength => wavelength_to_rgb(380 + (t * 400.0) as u32),
+            Palette::Fire => Rgb([
+                ((t * 3.0).min(1.0) * 255.0) as u8,
+                ((t * 3.0 - 1.0).clamp(0.0, 1.0) * 255.0) as u8,
+                ((t * 3.0 - 2.0).clamp(0.0, 1.0) * 255.0) as u8,
+            ]),
+        }
+    }
+
+    /// Approximates the colour of visible light, `wavelength` in nanometres
+    pub fn wavelength_to_rgb(wavelength: u32) -> Rgb<u8> {
+        let wave = wavelength as f32;
+
+        let (r, g, b) = match wavelength {
+            380..=439 => ((440. - wave) / (440. - 380.), 0.0, 1.0),
+            440..=489 => (0.0, (wave - 440.) / (490. - 440.), 1.0),
+            490..=509 => (0.0, 1.0, (510. - wave) / (510. - 490.)),
+            510..=579 => ((wave - 510.) / (580. - 510.), 1.0, 0.0),
+            580..=644 => (1.0, (645. - wave) / (645. - 580.), 0.0),
+            645..=780 => (1.0, 0.0, 0.0),
+            _ => (0.0, 0.0, 0.0),
+        };
+
+        // intensity falls off near the vision limits
+        let factor = match wavelength {
+            380..=419 => 0.3 + 0.7 * (wave - 380.) / (420. - 380.),
+            701..=780 => 0.3 + 0.7 * (780. - wave) / (780. - 700.),
+            _ => 1.0,
+        };
+
+        let (r, g, b) = (
+            normalize(r, factor),
+            normalize(g, factor),
+            normalize(b, factor),
+        );
+        Rgb([r, g, b])
+    }
+
+    fn normalize(color: f32, factor: f32) -> u8 {
+        ((color * factor).powf(0.8) * 255.) as u8
     }
 
-    for _ in 0..(width * height) {
-        let (x, y, pixel) = rx.recv().unwrap();
-        img.put_pixel(x, y, pixel);
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_render_matches_escape_time() {
+            let opts = FractalOptions {
+                width: 40,
+                height: 35,
+                max_iterations: 50,
+                palette: Palette::Grayscale,
+                ..Default::default()
+            };
+            let img = render(&opts);
+            assert_eq!(img.dimensions(), (40, 35));
+            for (x, y) in [(0, 0), (20, 17), (39, 34), (7, 33)] {
+                let i = escape_time(&opts, x, y);
+                assert_eq!(*img.get_pixel(x, y), colour(opts.palette, i as f64, 50));
+            }
+        }
+
+        #[test]
+        fn test_mandelbrot_points() {
+            let opts = FractalOptions {
+                width: 3,
+                height: 3,
+                mode: Mode::Mandelbrot,
+                ..Default::default()
+            };
+            // the centre pixel is 0+0i which never escapes
+            assert_eq!(escape_time(&opts, 1, 1), opts.max_iterations);
+            // corners are far outside the set
+            assert!(escape_time(&opts, 0, 0) < 5);
+        }
+
+        #[test]
+        fn test_zoom_viewports() {
+            let anim = ZoomAnimation {
+                start: FractalOptions {
+                    center: Complex::new(0.0, 0.0),
+                    ..Default::default()
+                },
+                target: Complex::new(-0.75, 0.1),
+                end_zoom: 100.0,
+                frames: 5,
+            };
+            let v = anim.viewports();
+            assert_eq!(v.len(), 5);
+            assert_eq!(v[0].zoom, 1.0);
+            assert!((v[4].zoom - 100.0).abs() < 1e-9);
+            assert!((v[2].zoom - 10.0).abs() < 1e-9);
+            assert!((v[4].center - anim.target).norm() < 0.01);
+        }
+
+        #[test]
+        fn test_smooth_escape_time_is_continuous() {
+            let opts = FractalOptions {
+                width: 200,
+                height: 1,
+                mode: Mode::Mandelbrot,
+                center: Complex::new(-1.5, 0.0),
+                ..Default::default()
+            };
+            // neighbouring pixels outside the set differ by a fraction of an iteration
+            let values: Vec<_> = (0..40).map(|x| smooth_escape_time(&opts, x, 0)).collect();
+            for w in values.windows(2) {
+                assert!((w[0] - w[1]).abs() < 1.0, "{:?}", w);
+            }
+        }
+
+        #[test]
+        fn test_render_zoom_png_sequence() {
+            let dir = tempfile::tempdir().unwrap();
+            let anim = ZoomAnimation {
+                start: FractalOptions {
+                    width: 16,
+                    height: 16,
+                    smooth: true,
+                    ..Default::default()
+                },
+                target: Complex::new(0.1, 0.1),
+                end_zoom: 4.0,
+                frames: 3,
+            };
+            render_zoom(&anim, &dir.path().join("zoom.png"), 40).unwrap();
+            for k in 0..3 {
+                assert!(dir.path().join(format!("zoom_{:04}.png", k)).exists());
+            }
+            render_zoom(&anim, &dir.path().join("zoom.gif"), 40).unwrap();
+            assert!(dir.path().join("zoom.gif").exists());
+        }
     }
-    let _ = img.save("output.png");
 }
 
-fn compute_digest(filepath: PathBuf) -> Result<(Digest, PathBuf), Error> {
+/// Reads the file once and feeds every chunk to all requested algorithms.
+pub fn compute_digest(
+    filepath: PathBuf,
+    algos: &[DigestAlgo],
+) -> Result<(Vec<Vec<u8>>, PathBuf), Error> {
     let mut buf_reader = BufReader::new(File::open(filepath.clone())?);
-    let mut context = Context::new(&SHA256);
+    let mut hashers: Vec<_> = algos.iter().map(|a| Hasher::new(*a)).collect();
     let mut buffer = [0; 1024];
 
     loop {
@@ -240,10 +939,10 @@ fn compute_digest(filepath: PathBuf) -> Result<(Digest, PathBuf), Error> {
         if count == 0 {
             break;
         }
-        context.update(&buffer[..count]);
+        hashers.iter_mut().for_each(|h| h.update(&buffer[..count]));
     }
 
-    Ok((context.finish(), filepath))
+    Ok((hashers.into_iter().map(Hasher::finish).collect(), filepath))
 }
 
 struct Person {
@@ -331,70 +1030,202 @@ fn rayon_experiments() {
 use std::fs::create_dir_all;
 use std::path::Path;
 
-use glob::{glob_with, MatchOptions};
 use image::imageops::FilterType;
-use image::ImageError;
+use image::{DynamicImage, ImageError, ImageFormat};
 use rayon::prelude::*;
 
-pub fn generate_thumbnails_parallel(path: &PathBuf) {
-    let options: MatchOptions = Default::default();
-    let p: String = path.to_str().unwrap().to_string() + "/*.jpg";
-    let files: Vec<_> = glob_with(&p, options)
-        .unwrap()
-        .filter_map(|x| x.ok())
-        .collect();
+const THUMBNAIL_SOURCES: [&str; 6] = ["jpg", "jpeg", "png", "gif", "bmp", "webp"];
 
-    if files.len() == 0 {
-        panic!("No .jpg files found in current directory");
+#[derive(Debug, Clone)]
+pub struct ThumbnailOptions {
+    pub longest_edge: u32,
+    pub filter: FilterType,
+    /// `None` keeps the format of the source image
+    pub format: Option<ImageFormat>,
+    /// defaults to `<DIR>/thumbnails`
+    pub thumb_dir: Option<PathBuf>,
+}
+
+impl Default for ThumbnailOptions {
+    fn default() -> Self {
+        ThumbnailOptions {
+            longest_edge: 300,
+            filter: FilterType::Lanczos3,
+            format: None,
+            thumb_dir: None,
+        }
     }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    /// source image, relative to the scanned directory
+    pub source: PathBuf,
+    pub path: PathBuf,
+}
 
-    let thumb_dir = path.to_str().unwrap().to_string() + "/thumbnails";
-    create_dir_all(&thumb_dir).unwrap();
+#[derive(Debug, Default)]
+pub struct ThumbnailSummary {
+    pub generated: usize,
+    pub up_to_date: usize,
+    pub failures: Vec<(PathBuf, ImageError)>,
+    /// images left alone because the run was cancelled
+    pub skipped: usize,
+    pub cancelled: Option<CancelReason>,
+    /// every thumbnail that exists after the run, sorted by source
+    pub thumbnails: Vec<Thumbnail>,
+}
 
-    println!("Saving {} thumbnails into '{}'...", files.len(), thumb_dir);
+pub fn parse_filter(name: &str) -> Result<FilterType, String> {
+    match name {
+        "nearest" => Ok(FilterType::Nearest),
+        "triangle" => Ok(FilterType::Triangle),
+        "catmullrom" => Ok(FilterType::CatmullRom),
+        "gaussian" => Ok(FilterType::Gaussian),
+        "lanczos3" => Ok(FilterType::Lanczos3),
+        _ => Err(format!("unknown filter '{}'", name)),
+    }
+}
 
-    let image_failures: Vec<_> = files
+/// Generates thumbnails for all images below `path`, mirroring the directory
+/// layout under the thumbnails directory. Thumbnails newer than their source
+/// are left alone. Once `cancel` fires the remaining images are skipped.
+pub fn generate_thumbnails_parallel(
+    path: &Path,
+    opts: &ThumbnailOptions,
+    cancel: &CancellationToken,
+) -> ThumbnailSummary {
+    let thumb_dir = opts
+        .thumb_dir
+        .clone()
+        .unwrap_or_else(|| path.join("thumbnails"));
+
+    let files: Vec<_> = WalkDir::new(path)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(|e| e.path() != thumb_dir)
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && is_thumbnail_source(e.path()))
+        .filter_map(|e| e.path().strip_prefix(path).ok().map(Path::to_path_buf))
+        .collect();
+
+    println!(
+        "Saving {} thumbnails into '{}'...",
+        files.len(),
+        thumb_dir.display()
+    );
+
+    let results: Vec<_> = files
         .par_iter()
         .map(|name| {
-            make_thumbnail(name.file_name().unwrap(), path, &thumb_dir, 300)
-            // .map_err(|e| e.chain_err(|| path.display().to_string()))
+            let res =
+                (!cancel.is_cancelled()).then(|| make_thumbnail(name, path, &thumb_dir, opts));
+            (name, res)
         })
-        .filter_map(|x| x.err())
         .collect();
 
-    image_failures.iter().for_each(|x| println!("{}", x));
-    // .for_each(|x| println!("{}", x.display_chain()));
-
+    let mut summary = ThumbnailSummary::default();
+    for (name, res) in results {
+        match res {
+            None => summary.skipped += 1,
+            Some(Ok(generated)) => {
+                if generated {
+                    summary.generated += 1;
+                } else {
+                    summary.up_to_date += 1;
+                }
+                summary.thumbnails.push(Thumbnail {
+                    source: name.clone(),
+                    path: thumbnail_path(&thumb_dir, name, opts.format),
+                });
+            }
+            Some(Err(e)) => summary.failures.push((name.clone(), e)),
+        }
+    }
+    summary.thumbnails.sort_by(|a, b| a.source.cmp(&b.source));
+    summary.cancelled = cancel.reason();
+
+    if let Some(reason) = summary.cancelled {
+        println!(
+            "Thumbnail generation {}, {} images skipped",
+            reason, summary.skipped
+        );
+    }
     println!(
-        "{} thumbnails saved successfully",
-        files.len() - image_failures.len()
+        "{} thumbnails saved successfully, {} already up to date, {} failed",
+        summary.generated,
+        summary.up_to_date,
+        summary.failures.len()
     );
+    summary
+        .failures
+        .iter()
+        .for_each(|(name, e)| println!("    {}: {}", name.display(), e));
+
+    summary
 }
 
+fn is_thumbnail_source(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| THUMBNAIL_SOURCES.contains(&e.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Path of the thumbnail for the image at `name`, relative to the source directory.
+/// A converted thumbnail keeps the source extension, `x.png` becomes `x.png.jpg`,
+/// so it can't clash with the thumbnail of `x.jpg`.
+pub fn thumbnail_path(thumb_dir: &Path, name: &Path, format: Option<ImageFormat>) -> PathBuf {
+    let target = thumb_dir.join(name);
+    let source_format = ImageFormat::from_path(name).ok();
+    let extension = match format.or(source_format) {
+        // webp can be decoded but not encoded
+        Some(ImageFormat::WebP) => "png",
+        Some(f) if Some(f) != source_format => f.extensions_str().first().unwrap_or(&"png"),
+        _ => return target,
+    };
+    let mut converted = target.into_os_string();
+    converted.push(".");
+    converted.push(extension);
+    PathBuf::from(converted)
+}
+
+/// Returns `false` if an up-to-date thumbnail already exists
 fn make_thumbnail<PA, PB, PC>(
     name: PC,
     original: PA,
     thumb_dir: PB,
-    longest_edge: u32,
-) -> Result<(), ImageError>
+    opts: &ThumbnailOptions,
+) -> Result<bool, ImageError>
 where
     PA: AsRef<Path>,
     PB: AsRef<Path>,
     PC: AsRef<Path>,
 {
-    println!(
-        "name: {:?}, original: {:?}, thumb_dir: {:?}",
-        name.as_ref(),
-        original.as_ref(),
-        thumb_dir.as_ref()
-    );
-    let img = image::open(original.as_ref().join(&name))?;
-    let file_path = thumb_dir.as_ref().join(name);
+    let source = original.as_ref().join(&name);
+    let file_path = thumbnail_path(thumb_dir.as_ref(), name.as_ref(), opts.format);
+
+    let modified = |p: &Path| p.metadata().and_then(|m| m.modified()).ok();
+    if let (Some(src), Some(thumb)) = (modified(&source), modified(&file_path)) {
+        if thumb >= src {
+            return Ok(false);
+        }
+    }
+
+    let img = image::open(&source)?;
+    if let Some(parent) = file_path.parent() {
+        create_dir_all(parent)?;
+    }
     println!("Saving at {:?}...", file_path);
 
-    Ok(img
-        .resize(longest_edge, longest_edge, FilterType::Nearest)
-        .save(file_path)?)
+    let thumb = img.resize(opts.longest_edge, opts.longest_edge, opts.filter);
+    let thumb = match ImageFormat::from_path(&file_path) {
+        // jpeg has no alpha channel
+        Ok(ImageFormat::Jpeg) => DynamicImage::ImageRgb8(thumb.to_rgb8()),
+        _ => thumb,
+    };
+    thumb.save(file_path)?;
+    Ok(true)
 }
 
 #[cfg(test)]
@@ -405,4 +1236,152 @@ mod test {
     fn test_short_lived_threads() {
         short_lived_threads();
     }
+
+    #[test]
+    fn test_sha256sum_line_round_trip() {
+        let digest = [0xabu8; 32];
+        for name in ["dir/file.txt", "with space", "back\\slash", "new\nline"] {
+            for tag in [None, Some(DigestAlgo::Sha256)] {
+                let line = sha256sum_line(&digest, Path::new(name), tag);
+                let (algo, hex, path) = parse_sha256sum_line(&line, DigestAlgo::Sha256).unwrap();
+                assert_eq!(algo, DigestAlgo::Sha256);
+                assert_eq!(hex, HEXLOWER.encode(&digest));
+                assert_eq!(path, PathBuf::from(name));
+            }
+        }
+        assert!(sha256sum_line(&digest, Path::new("new\nline"), None).starts_with('\\'));
+        assert!(parse_sha256sum_line("abc  file", DigestAlgo::Sha256).is_none());
+        assert!(parse_sha256sum_line(&"0".repeat(64), DigestAlgo::Sha512).is_none());
+    }
+
+    #[test]
+    fn test_sha256_check() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("hello.txt");
+        File::create(&file).unwrap().write_all(b"hello\n").unwrap();
+
+        let list = dir.path().join("SHA256SUMS");
+        let good = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+        std::fs::write(&list, format!("{}  {}\n", good, file.display())).unwrap();
+        assert!(sha256_check(&list, DigestAlgo::Sha256));
+
+        std::fs::write(&list, format!("{}  {}\n", "0".repeat(64), file.display())).unwrap();
+        assert!(!sha256_check(&list, DigestAlgo::Sha256));
+
+        let sha1 = "f572d396fae9206628714fb2ce00f72e94f2258f";
+        std::fs::write(&list, format!("SHA1 ({}) = {}\n", file.display(), sha1)).unwrap();
+        assert!(sha256_check(&list, DigestAlgo::Sha256));
+    }
+
+    #[test]
+    fn test_sha256_files_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..3 {
+            std::fs::write(dir.path().join(i.to_string()), [i]).unwrap();
+        }
+        let path = dir.path().to_path_buf();
+
+        let summary = sha256_files(
+            &path,
+            &[DigestAlgo::Sha256],
+            None,
+            &CancellationToken::new(),
+        );
+        assert_eq!(
+            (summary.hashed, summary.skipped, summary.cancelled),
+            (3, 0, None)
+        );
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let summary = sha256_files(&path, &[DigestAlgo::Sha256], None, &cancel);
+        assert_eq!(summary.hashed, 0);
+        assert_eq!(summary.cancelled, Some(CancelReason::Interrupted));
+    }
+
+    #[test]
+    fn test_compute_digest_single_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("hello.txt");
+        std::fs::write(&file, b"hello\n").unwrap();
+
+        let (digests, _) = compute_digest(file, &DigestAlgo::ALL).unwrap();
+        let hex: Vec<_> = digests.iter().map(|d| HEXLOWER.encode(d)).collect();
+        assert_eq!(hex[0], "f572d396fae9206628714fb2ce00f72e94f2258f");
+        assert_eq!(
+            hex[1],
+            "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03"
+        );
+        assert_eq!(hex[2].len(), 96);
+        assert_eq!(hex[3].len(), 128);
+        assert_eq!(hex[4].len(), 16);
+
+        let mut fnv = Hasher::new(DigestAlgo::Fnv1a64);
+        fnv.update(b"a");
+        assert_eq!(HEXLOWER.encode(&fnv.finish()), "af63dc4c8601ec8c");
+        assert_eq!("sha384".parse::<DigestAlgo>(), Ok(DigestAlgo::Sha384));
+        assert!("md5".parse::<DigestAlgo>().is_err());
+    }
+
+    #[test]
+    fn test_generate_thumbnails_parallel() {
+        use image::{Rgb, RgbImage};
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("holiday/day1")).unwrap();
+        let img = RgbImage::from_pixel(64, 32, Rgb([10, 200, 30]));
+        img.save(root.join("a.jpg")).unwrap();
+        img.save(root.join("holiday/day1/b.PNG")).unwrap();
+        img.save(root.join("holiday/c.bmp")).unwrap();
+        std::fs::write(root.join("holiday/broken.gif"), b"not a gif").unwrap();
+        std::fs::write(root.join("notes.txt"), b"ignored").unwrap();
+
+        let opts = ThumbnailOptions {
+            longest_edge: 16,
+            ..Default::default()
+        };
+        let summary = generate_thumbnails_parallel(root, &opts, &CancellationToken::new());
+        assert_eq!(summary.generated, 3);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(summary.failures[0].0, Path::new("holiday/broken.gif"));
+
+        let thumb = image::open(root.join("thumbnails/holiday/day1/b.PNG")).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (16, 8));
+        assert!(root.join("thumbnails/holiday/c.bmp").exists());
+
+        // nothing changed, so nothing is regenerated, not even the thumbnails themselves
+        let summary = generate_thumbnails_parallel(root, &opts, &CancellationToken::new());
+        assert_eq!((summary.generated, summary.up_to_date), (0, 3));
+
+        let opts = ThumbnailOptions {
+            format: Some(ImageFormat::Jpeg),
+            ..opts
+        };
+        assert_eq!(
+            thumbnail_path(Path::new("t"), Path::new("x/b.png"), opts.format),
+            Path::new("t/x/b.png.jpg")
+        );
+        assert_eq!(
+            thumbnail_path(Path::new("t"), Path::new("b.jpg"), opts.format),
+            Path::new("t/b.jpg")
+        );
+        assert_eq!(
+            thumbnail_path(Path::new("t"), Path::new("b.webp"), None),
+            Path::new("t/b.webp.png")
+        );
+        // a.jpg already has an up to date jpg thumbnail
+        let summary = generate_thumbnails_parallel(root, &opts, &CancellationToken::new());
+        assert_eq!((summary.generated, summary.up_to_date), (2, 1));
+        assert!(root.join("thumbnails/holiday/day1/b.PNG.jpg").exists());
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let summary = generate_thumbnails_parallel(root, &opts, &cancel);
+        assert_eq!(summary.cancelled, Some(CancelReason::Interrupted));
+        assert_eq!((summary.generated, summary.skipped), (0, 4));
+        assert!(summary.thumbnails.is_empty());
+    }
 }
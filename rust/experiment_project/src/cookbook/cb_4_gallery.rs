@@ -0,0 +1,413 @@
+// Static HTML gallery and contact sheet built from the output of
+// cb_4_concurrency::generate_thumbnails_parallel
+
+use super::cb_4_concurrency::Thumbnail;
+use image::{imageops, ImageResult, Rgb, RgbImage};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Characters that have to be escaped in a relative URL path, `&` and `'`
+/// too so that the URL can go into an HTML attribute as it is
+const PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'\'')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+const PADDING: u32 = 8;
+const CAPTION_HEIGHT: u32 = GLYPH_HEIGHT + 6;
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const TEXT: Rgb<u8> = Rgb([40, 40, 40]);
+
+/// Writes `<root>/index.html` where every thumbnail links to its original,
+/// together with the original dimensions and file size.
+pub fn write_html_gallery(root: &Path, thumbnails: &[Thumbnail]) -> Result<PathBuf, Error> {
+    let index = root.join("index.html");
+    let mut out = BufWriter::new(File::create(&index)?);
+    let base = root.canonicalize()?;
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(
+        out,
+        "<title>{}</title>",
+        html_escape(&root.display().to_string())
+    )?;
+    writeln!(
+        out,
+        "<style>\n\
+         figure {{ display: inline-block; margin: 8px; text-align: center; vertical-align: top; }}\n\
+         figcaption {{ font: 12px sans-serif; }}\n\
+         </style>\n</head>\n<body>"
+    )?;
+
+    for thumb in thumbnails {
+        let original = root.join(&thumb.source);
+        let size = original.metadata().map(|m| m.len()).unwrap_or(0);
+        let dimensions = image::image_dimensions(&original)
+            .map(|(w, h)| format!("{}&times;{}", w, h))
+            .unwrap_or_else(|_| "?".to_string());
+        // the thumbnail directory may be anywhere, relative to the working directory
+        let thumb_src = relative_path(&thumb.path.canonicalize()?, &base);
+
+        writeln!(
+            out,
+            "<figure>\n\
+             <a href=\"{}\"><img src=\"{}\" alt=\"{}\"></a>\n\
+             <figcaption>{}<br>{}, {}</figcaption>\n\
+             </figure>",
+            url_path(&thumb.source),
+            url_path(&thumb_src),
+            html_escape(&thumb.source.display().to_string()),
+            html_escape(&thumb.source.display().to_string()),
+            dimensions,
+            human_size(size)
+        )?;
+    }
+
+    writeln!(out, "</body>\n</html>")?;
+    out.flush()?;
+    Ok(index)
+}
+
+/// Composites all thumbnails into a grid of cells, each one with the file
+/// name as a caption. By default the grid is roughly square. Cells are
+/// `cell` pixels square, or larger if a thumbnail is, e.g. one left over
+/// from an earlier run with a bigger size.
+pub fn contact_sheet(
+    thumbnails: &[Thumbnail],
+    cell: u32,
+    columns: Option<u32>,
+) -> ImageResult<RgbImage> {
+    let n = thumbnails.len() as u32;
+    let columns = columns
+        .unwrap_or_else(|| (n as f64).sqrt().ceil() as u32)
+        .clamp(1, n.max(1));
+    let rows = n.div_ceil(columns);
+
+    let images = thumbnails
+        .par_iter()
+        .map(|t| image::open(&t.path).map(|img| img.to_rgb8()))
+        .collect::<ImageResult<Vec<_>>>()?;
+
+    let width = images.iter().map(|i| i.width()).fold(cell, u32::max);
+    let height = images.iter().map(|i| i.height()).fold(cell, u32::max);
+    let (cell_w, cell_h) = (width + PADDING, height + PADDING + CAPTION_HEIGHT);
+
+    let mut sheet = RgbImage::from_pixel(
+        columns * cell_w + PADDING,
+        rows.max(1) * cell_h + PADDING,
+        BACKGROUND,
+    );
+    for (k, (thumb, img)) in thumbnails.iter().zip(images).enumerate() {
+        let (col, row) = (k as u32 % columns, k as u32 / columns);
+        let (x0, y0) = (PADDING + col * cell_w, PADDING + row * cell_h);
+
+        // centre the thumbnail in its cell
+        let x = x0 + (width - img.width()) / 2;
+        let y = y0 + (height - img.height()) / 2;
+        imageops::overlay(&mut sheet, &img, x as i64, y as i64);
+
+        let name = thumb
+            .source
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let caption = fit_caption(&name, width);
+        let text_x = x0 + width.saturating_sub(text_width(&caption)) / 2;
+        draw_text(&mut sheet, text_x, y0 + height + 3, &caption, TEXT);
+    }
+
+    Ok(sheet)
+}
+
+// `path` as seen from the directory `base`, both absolute
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let ups = base.components().skip(common).map(|_| Component::ParentDir);
+    ups.chain(path.components().skip(common)).collect()
+}
+
+fn url_path(path: &Path) -> String {
+    path.components()
+        .map(|c| utf8_percent_encode(&c.as_os_str().to_string_lossy(), PATH).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+// shortens the name with a '~' in the middle, so that it fits into the cell
+fn fit_caption(name: &str, width: u32) -> String {
+    let max_chars = (width / GLYPH_ADVANCE) as usize;
+    let chars: Vec<char> = name.chars().collect();
+    if chars.len() <= max_chars || max_chars < 3 {
+        return chars.into_iter().take(max_chars).collect();
+    }
+    let head = (max_chars - 1) / 2;
+    let tail = max_chars - 1 - head;
+    let mut caption: String = chars[..head].iter().collect();
+    caption.push('~');
+    caption.extend(&chars[chars.len() - tail..]);
+    caption
+}
+
+// 5x7 bitmap font for printable ASCII, one byte per column, bit 0 at the top
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const GLYPH_ADVANCE: u32 = GLYPH_WIDTH + 1;
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x00, 0x08, 0x14, 0x22, 0x41], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x41, 0x22, 0x14, 0x08, 0x00], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // @
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x01, 0x01], // F
+    [0x3E, 0x41, 0x41, 0x51, 0x32], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x04, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x7F, 0x20, 0x18, 0x20, 0x7F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x03, 0x04, 0x78, 0x04, 0x03], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x00, 0x7F, 0x41, 0x41], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x41, 0x41, 0x7F, 0x00, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // f
+    [0x08, 0x14, 0x54, 0x54, 0x3C], // g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // j
+    [0x00, 0x7F, 0x10, 0x28, 0x44], // k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // l
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // t
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x10, 0x08, 0x08, 0x10, 0x08], // ~
+];
+
+fn text_width(text: &str) -> u32 {
+    (text.chars().count() as u32 * GLYPH_ADVANCE).saturating_sub(1)
+}
+
+/// Draws `text` with its top left corner at `(x, y)`, characters outside
+/// of printable ASCII are drawn as '?'
+fn draw_text(img: &mut RgbImage, x: u32, y: u32, text: &str, color: Rgb<u8>) {
+    for (k, ch) in text.chars().enumerate() {
+        let glyph = match ch {
+            ' '..='~' => FONT[ch as usize - ' ' as usize],
+            _ => FONT['?' as usize - ' ' as usize],
+        };
+        let gx = x + k as u32 * GLYPH_ADVANCE;
+        for (col, bits) in glyph.iter().enumerate() {
+            for row in 0..GLYPH_HEIGHT {
+                let (px, py) = (gx + col as u32, y + row);
+                if bits & (1 << row) != 0 && px < img.width() && py < img.height() {
+                    img.put_pixel(px, py, color);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gallery_and_contact_sheet() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("a b")).unwrap();
+        std::fs::create_dir_all(root.join("thumbnails/a b")).unwrap();
+
+        let mut thumbnails = Vec::new();
+        for (k, name) in ["a b/x&y.png", "long_file_name_of_a_photo.png", "z.png"]
+            .iter()
+            .enumerate()
+        {
+            let source = PathBuf::from(name);
+            RgbImage::from_pixel(80, 40, Rgb([k as u8 * 100, 0, 0]))
+                .save(root.join(&source))
+                .unwrap();
+            let path = root.join("thumbnails").join(&source);
+            RgbImage::from_pixel(20, 10, Rgb([0, 0, 255]))
+                .save(&path)
+                .unwrap();
+            thumbnails.push(Thumbnail { source, path });
+        }
+
+        let index = write_html_gallery(root, &thumbnails).unwrap();
+        let html = std::fs::read_to_string(index).unwrap();
+        assert!(
+            html.contains("<a href=\"a%20b/x%26y.png\"><img src=\"thumbnails/a%20b/x%26y.png\"")
+        );
+        assert!(html.contains("a b/x&amp;y.png<br>80&times;40"));
+        assert_eq!(html.matches("<figure>").count(), 3);
+
+        let sheet = contact_sheet(&thumbnails, 20, None).unwrap();
+        // 3 thumbnails make a 2x2 grid
+        assert_eq!(sheet.width(), 2 * (20 + PADDING) + PADDING);
+        assert_eq!(
+            sheet.height(),
+            2 * (20 + PADDING + CAPTION_HEIGHT) + PADDING
+        );
+        assert_eq!(*sheet.get_pixel(PADDING + 5, PADDING + 8), Rgb([0, 0, 255]));
+        assert!(sheet.pixels().any(|p| *p == TEXT));
+    }
+
+    #[test]
+    fn test_thumbnails_outside_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("photos");
+        let out = dir.path().join("thumbs");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&out).unwrap();
+
+        let mut thumbnails = Vec::new();
+        for (name, size) in [("big.png", 40), ("small.png", 10)] {
+            let source = PathBuf::from(name);
+            RgbImage::new(size, size).save(root.join(&source)).unwrap();
+            let path = out.join(&source);
+            RgbImage::from_pixel(size, size / 2, Rgb([0, 0, 255]))
+                .save(&path)
+                .unwrap();
+            thumbnails.push(Thumbnail { source, path });
+        }
+
+        let index = write_html_gallery(&root, &thumbnails).unwrap();
+        let html = std::fs::read_to_string(index).unwrap();
+        assert!(html.contains("<img src=\"../thumbs/big.png\""));
+
+        // the 40 pixel thumbnail from an earlier run widens every cell
+        let sheet = contact_sheet(&thumbnails, 20, None).unwrap();
+        assert_eq!(sheet.width(), 2 * (40 + PADDING) + PADDING);
+        assert_eq!(sheet.height(), 20 + PADDING + CAPTION_HEIGHT + PADDING);
+        assert_eq!(
+            *sheet.get_pixel(2 * PADDING + 40 + 15, PADDING + 10),
+            Rgb([0, 0, 255])
+        );
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let rel = |p: &str, b: &str| relative_path(Path::new(p), Path::new(b));
+        assert_eq!(rel("/a/b/c.png", "/a"), PathBuf::from("b/c.png"));
+        assert_eq!(rel("/a/t/c.png", "/a/b"), PathBuf::from("../t/c.png"));
+        assert_eq!(
+            rel("/tmp/c.png", "/home/u"),
+            PathBuf::from("../../tmp/c.png")
+        );
+    }
+
+    #[test]
+    fn test_captions() {
+        assert_eq!(fit_caption("short.png", 300), "short.png");
+        assert_eq!(fit_caption("a_very_long_file_name.jpg", 60), "a_ve~e.jpg");
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(3 * 1024 * 1024 / 2), "1.5 MiB");
+    }
+}
@@ -0,0 +1,282 @@
+// In-process key-value store shared between threads. The keys are spread over
+// a number of shards, each behind its own RwLock, so readers never block each
+// other and writers only contend when they hit the same shard.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+struct Entry<V> {
+    value: V,
+    expires: Option<Instant>,
+}
+
+impl<V> Entry<V> {
+    fn is_live(&self, now: Instant) -> bool {
+        self.expires.is_none_or(|t| now < t)
+    }
+}
+
+type Shard<K, V> = RwLock<HashMap<K, Entry<V>>>;
+
+pub struct ShardedStore<K, V> {
+    shards: Vec<Shard<K, V>>,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Default for ShardedStore<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> ShardedStore<K, V> {
+    /// Four shards per CPU, rounded up to a power of two
+    pub fn new() -> Self {
+        Self::with_shards((num_cpus::get() * 4).next_power_of_two())
+    }
+
+    pub fn with_shards(n: usize) -> Self {
+        ShardedStore {
+            shards: (0..n.max(1)).map(|_| RwLock::new(HashMap::new())).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn shard(&self, key: &K) -> &Shard<K, V> {
+        let i = self.hasher.hash_one(key) as usize % self.shards.len();
+        &self.shards[i]
+    }
+
+    // a panicking writer leaves every entry whole, so poisoning is ignored
+    fn read(shard: &Shard<K, V>) -> RwLockReadGuard<'_, HashMap<K, Entry<V>>> {
+        shard.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(shard: &Shard<K, V>) -> RwLockWriteGuard<'_, HashMap<K, Entry<V>>> {
+        shard.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let now = Instant::now();
+        Self::read(self.shard(key))
+            .get(key)
+            .filter(|e| e.is_live(now))
+            .map(|e| e.value.clone())
+    }
+
+    /// Stores `value` without expiry, returns the previous value
+    pub fn put(&self, key: K, value: V) -> Option<V> {
+        self.insert(key, value, None)
+    }
+
+    /// Stores `value` which disappears after `ttl`, returns the previous value
+    pub fn put_with_ttl(&self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.insert(key, value, Instant::now().checked_add(ttl))
+    }
+
+    fn insert(&self, key: K, value: V, expires: Option<Instant>) -> Option<V> {
+        let now = Instant::now();
+        Self::write(self.shard(&key))
+            .insert(key, Entry { value, expires })
+            .filter(|e| e.is_live(now))
+            .map(|e| e.value)
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let now = Instant::now();
+        Self::write(self.shard(key))
+            .remove(key)
+            .filter(|e| e.is_live(now))
+            .map(|e| e.value)
+    }
+
+    /// Replaces the value of `key` with `new` if it currently is `expected`,
+    /// `None` standing for a missing key on either side. On a mismatch the
+    /// current value is returned and nothing changes.
+    pub fn compare_and_swap(
+        &self,
+        key: K,
+        expected: Option<&V>,
+        new: Option<V>,
+    ) -> Result<(), Option<V>>
+    where
+        V: PartialEq,
+    {
+        let now = Instant::now();
+        let mut shard = Self::write(self.shard(&key));
+        let current = shard.get(&key).filter(|e| e.is_live(now)).map(|e| &e.value);
+        if current != expected {
+            return Err(current.cloned());
+        }
+        match new {
+            Some(value) => shard.insert(
+                key,
+                Entry {
+                    value,
+                    expires: None,
+                },
+            ),
+            None => shard.remove(&key),
+        };
+        Ok(())
+    }
+
+    /// Copies all the live entries. All the shards are read locked at once,
+    /// so the snapshot is a single point in time: it never mixes a write with
+    /// one that happened before it but was left out.
+    pub fn snapshot(&self) -> Vec<(K, V)> {
+        let now = Instant::now();
+        // every writer holds a single shard lock, so taking them all in order can't deadlock
+        let guards: Vec<_> = self.shards.iter().map(Self::read).collect();
+        guards
+            .iter()
+            .flat_map(|shard| shard.iter())
+            .filter(|(_, e)| e.is_live(now))
+            .map(|(k, e)| (k.clone(), e.value.clone()))
+            .collect()
+    }
+
+    /// Number of live entries
+    pub fn len(&self) -> usize {
+        let now = Instant::now();
+        self.shards
+            .iter()
+            .map(|s| Self::read(s).values().filter(|e| e.is_live(now)).count())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Expired entries are invisible but keep their memory until purged,
+    /// returns how many were dropped
+    pub fn purge_expired(&self) -> usize {
+        let now = Instant::now();
+        self.shards
+            .iter()
+            .map(|s| {
+                let mut shard = Self::write(s);
+                let before = shard.len();
+                shard.retain(|_, e| e.is_live(now));
+                before - shard.len()
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_basic_operations() {
+        let store = ShardedStore::with_shards(3);
+        assert_eq!(store.put("a", 1), None);
+        assert_eq!(store.put("a", 2), Some(1));
+        assert_eq!(store.get(&"a"), Some(2));
+        assert_eq!(store.compare_and_swap("a", Some(&1), Some(5)), Err(Some(2)));
+        assert_eq!(store.compare_and_swap("a", Some(&2), Some(5)), Ok(()));
+        assert_eq!(store.compare_and_swap("b", None, Some(7)), Ok(()));
+        assert_eq!(store.compare_and_swap("b", Some(&7), None), Ok(()));
+        assert_eq!(store.get(&"b"), None);
+        assert_eq!(store.remove(&"a"), Some(5));
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_ttl() {
+        let store = ShardedStore::with_shards(2);
+        store.put_with_ttl("session", 1, Duration::from_millis(20));
+        store.put("config", 2);
+        assert_eq!(store.get(&"session"), Some(1));
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(store.get(&"session"), None);
+        assert_eq!(store.snapshot(), vec![("config", 2)]);
+        assert_eq!(store.len(), 1);
+        // an expired entry counts as missing for compare-and-swap
+        assert_eq!(
+            store.compare_and_swap("session", Some(&1), Some(3)),
+            Err(None)
+        );
+        store.put_with_ttl("session", 1, Duration::ZERO);
+        assert_eq!(store.purge_expired(), 1);
+    }
+
+    // Every writer bumps a shared counter with compare-and-swap and then writes
+    // its own pair of keys, first `a` and then `b`, with increasing values.
+    // Readers check that nothing ever goes backwards and that no snapshot
+    // shows a `b` newer than its `a`.
+    #[test]
+    fn test_stress_linearisable() {
+        const WRITERS: usize = 4;
+        const READERS: usize = 4;
+        const ROUNDS: u64 = 2000;
+
+        let store: ShardedStore<String, u64> = ShardedStore::with_shards(8);
+        let done = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            let writers: Vec<_> = (0..WRITERS)
+                .map(|w| {
+                    let store = &store;
+                    s.spawn(move || {
+                        for round in 1..=ROUNDS {
+                            loop {
+                                let current = store.get(&"counter".to_string());
+                                let next = current.unwrap_or(0) + 1;
+                                if store
+                                    .compare_and_swap(
+                                        "counter".to_string(),
+                                        current.as_ref(),
+                                        Some(next),
+                                    )
+                                    .is_ok()
+                                {
+                                    break;
+                                }
+                            }
+                            store.put(format!("a{}", w), round);
+                            store.put(format!("b{}", w), round);
+                        }
+                    })
+                })
+                .collect();
+
+            for _ in 0..READERS {
+                s.spawn(|| {
+                    let mut last_counter = 0;
+                    while !done.load(Ordering::Acquire) {
+                        let counter = store.get(&"counter".to_string()).unwrap_or(0);
+                        assert!(counter >= last_counter, "counter went backwards");
+                        last_counter = counter;
+
+                        let snap: HashMap<_, _> = store.snapshot().into_iter().collect();
+                        for w in 0..WRITERS {
+                            let a = snap.get(&format!("a{}", w)).copied().unwrap_or(0);
+                            let b = snap.get(&format!("b{}", w)).copied().unwrap_or(0);
+                            assert!(a == b || a == b + 1, "a{} = {}, b{} = {}", w, a, w, b);
+                        }
+                    }
+                });
+            }
+
+            writers.into_iter().for_each(|w| w.join().unwrap());
+            done.store(true, Ordering::Release);
+        });
+
+        assert_eq!(
+            store.get(&"counter".to_string()),
+            Some(WRITERS as u64 * ROUNDS)
+        );
+        for w in 0..WRITERS {
+            assert_eq!(store.get(&format!("b{}", w)), Some(ROUNDS));
+        }
+        assert_eq!(store.len(), 1 + 2 * WRITERS);
+    }
+}
@@ -0,0 +1,325 @@
+// Divide-and-conquer algorithms over slices, generalising
+// cb_4_concurrency::find_max. The halves are processed with `rayon::join`,
+// which reuses the pool threads instead of spawning two new threads per split,
+// and everything below the cutoff runs sequentially.
+
+use rayon::prelude::*;
+
+/// Slices shorter than this are never split when the cutoff is chosen automatically
+const MIN_AUTO_CUTOFF: usize = 4096;
+
+/// Length below which an algorithm switches to its sequential version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cutoff {
+    /// about four chunks per pool thread, but never below 4096 elements
+    #[default]
+    Auto,
+    Fixed(usize),
+}
+
+impl Cutoff {
+    pub fn resolve(self, len: usize) -> usize {
+        match self {
+            Cutoff::Auto => (len / (4 * rayon::current_num_threads())).max(MIN_AUTO_CUTOFF),
+            Cutoff::Fixed(n) => n.max(1),
+        }
+    }
+}
+
+/// Folds `data` with an associative `op`, `identity` must be neutral for `op`
+pub fn reduce<T, F>(data: &[T], identity: T, op: F, cutoff: Cutoff) -> T
+where
+    T: Clone + Send + Sync,
+    F: Fn(T, T) -> T + Sync,
+{
+    map_reduce(data, |x| x.clone(), identity, op, cutoff)
+}
+
+/// Maps every element and folds the results with an associative `op`
+pub fn map_reduce<T, R, M, F>(data: &[T], map: M, identity: R, op: F, cutoff: Cutoff) -> R
+where
+    T: Sync,
+    R: Clone + Send + Sync,
+    M: Fn(&T) -> R + Sync,
+    F: Fn(R, R) -> R + Sync,
+{
+    fn go<T, R, M, F>(data: &[T], map: &M, identity: &R, op: &F, cutoff: usize) -> R
+    where
+        T: Sync,
+        R: Clone + Send + Sync,
+        M: Fn(&T) -> R + Sync,
+        F: Fn(R, R) -> R + Sync,
+    {
+        if data.len() <= cutoff {
+            return data.iter().fold(identity.clone(), |acc, x| op(acc, map(x)));
+        }
+        let (left, right) = data.split_at(data.len() / 2);
+        let (l, r) = rayon::join(
+            || go(left, map, identity, op, cutoff),
+            || go(right, map, identity, op, cutoff),
+        );
+        op(l, r)
+    }
+
+    go(data, &map, &identity, &op, cutoff.resolve(data.len()))
+}
+
+/// Stable parallel merge sort
+pub fn merge_sort<T>(data: &mut [T], cutoff: Cutoff)
+where
+    T: Ord + Clone + Send,
+{
+    fn go<T: Ord + Clone + Send>(data: &mut [T], buf: &mut [T], cutoff: usize) {
+        if data.len() <= cutoff {
+            data.sort();
+            return;
+        }
+        let mid = data.len() / 2;
+        {
+            let (dl, dr) = data.split_at_mut(mid);
+            let (bl, br) = buf.split_at_mut(mid);
+            rayon::join(|| go(dl, bl, cutoff), || go(dr, br, cutoff));
+        }
+        merge(&data[..mid], &data[mid..], buf);
+        data.clone_from_slice(buf);
+    }
+
+    let cutoff = cutoff.resolve(data.len());
+    if data.len() <= cutoff {
+        data.sort();
+        return;
+    }
+    let mut buf = data.to_vec();
+    go(data, &mut buf, cutoff);
+}
+
+// on ties the left element goes first, which keeps the sort stable
+fn merge<T: Ord + Clone>(left: &[T], right: &[T], out: &mut [T]) {
+    let (mut i, mut j) = (0, 0);
+    for slot in out.iter_mut() {
+        if j >= right.len() || (i < left.len() && left[i] <= right[j]) {
+            *slot = left[i].clone();
+            i += 1;
+        } else {
+            *slot = right[j].clone();
+            j += 1;
+        }
+    }
+}
+
+/// The `k`-th smallest element (0-based), partitions are computed in parallel
+pub fn quickselect<T>(data: &[T], k: usize, cutoff: Cutoff) -> Option<T>
+where
+    T: Ord + Clone + Send + Sync,
+{
+    if k >= data.len() {
+        return None;
+    }
+    let cutoff = cutoff.resolve(data.len());
+    let mut data = data.to_vec();
+    let mut k = k;
+
+    loop {
+        if data.len() <= cutoff {
+            let (_, kth, _) = data.select_nth_unstable(k);
+            return Some(kth.clone());
+        }
+
+        let pivot = median_of_three(&data);
+        let (n_less, n_equal) = data
+            .par_iter()
+            .map(|x| match x.cmp(&pivot) {
+                std::cmp::Ordering::Less => (1, 0),
+                std::cmp::Ordering::Equal => (0, 1),
+                std::cmp::Ordering::Greater => (0, 0),
+            })
+            .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+
+        if k < n_less {
+            data = data.into_par_iter().filter(|x| *x < pivot).collect();
+        } else if k < n_less + n_equal {
+            return Some(pivot);
+        } else {
+            data = data.into_par_iter().filter(|x| *x > pivot).collect();
+            k -= n_less + n_equal;
+        }
+    }
+}
+
+fn median_of_three<T: Ord + Clone>(data: &[T]) -> T {
+    let mut three = [&data[0], &data[data.len() / 2], &data[data.len() - 1]];
+    three.sort();
+    three[1].clone()
+}
+
+/// Inclusive scan with an associative `op`: `out[i] = data[0] op ... op data[i]`.
+/// Chunk totals are computed in parallel, scanned sequentially and then every
+/// chunk is scanned in parallel starting from the total of the chunks before it.
+pub fn scan<T, F>(data: &[T], op: F, cutoff: Cutoff) -> Vec<T>
+where
+    T: Clone + Send + Sync,
+    F: Fn(&T, &T) -> T + Sync,
+{
+    let chunk = cutoff.resolve(data.len());
+
+    let totals: Vec<T> = data.par_chunks(chunk).map(|c| fold_first(c, &op)).collect();
+
+    // offsets[i] is the total of all the chunks before chunk i
+    let mut offsets: Vec<Option<T>> = Vec::with_capacity(totals.len());
+    let mut acc: Option<T> = None;
+    for total in &totals {
+        offsets.push(acc.clone());
+        acc = Some(match acc {
+            Some(a) => op(&a, total),
+            None => total.clone(),
+        });
+    }
+
+    let mut out = data.to_vec();
+    out.par_chunks_mut(chunk)
+        .zip(offsets.into_par_iter())
+        .for_each(|(c, offset)| {
+            let mut acc = offset;
+            for x in c.iter_mut() {
+                let next = match &acc {
+                    Some(a) => op(a, x),
+                    None => x.clone(),
+                };
+                *x = next.clone();
+                acc = Some(next);
+            }
+        });
+    out
+}
+
+/// Running totals, `scan` with `+`
+pub fn prefix_sum<T>(data: &[T], cutoff: Cutoff) -> Vec<T>
+where
+    T: Copy + Send + Sync + std::ops::Add<Output = T>,
+{
+    scan(data, |a, b| *a + *b, cutoff)
+}
+
+fn fold_first<T: Clone, F: Fn(&T, &T) -> T>(chunk: &[T], op: &F) -> T {
+    let (first, rest) = chunk.split_first().unwrap();
+    rest.iter().fold(first.clone(), |acc, x| op(&acc, x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{thread_rng, Rng};
+
+    const CUTOFFS: [Cutoff; 4] = [
+        Cutoff::Fixed(1),
+        Cutoff::Fixed(7),
+        Cutoff::Fixed(1000),
+        Cutoff::Auto,
+    ];
+
+    fn random_vec(len: usize, max: i64) -> Vec<i64> {
+        let mut rng = thread_rng();
+        (0..len).map(|_| rng.gen_range(-max..=max)).collect()
+    }
+
+    #[test]
+    fn test_reduce_matches_sequential() {
+        for len in [0, 1, 2, 3, 100, 10_000, 50_000] {
+            let v = random_vec(len, 1_000_000);
+            for cutoff in CUTOFFS {
+                assert_eq!(reduce(&v, 0, |a, b| a + b, cutoff), v.iter().sum::<i64>());
+                assert_eq!(
+                    map_reduce(&v, |x| Some(*x), None, Option::max, cutoff),
+                    v.iter().copied().max()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_sort_matches_sequential() {
+        for len in [0, 1, 2, 5, 1000, 30_000] {
+            // many duplicates
+            let v = random_vec(len, 50);
+            let mut expected = v.clone();
+            expected.sort();
+            for cutoff in CUTOFFS {
+                let mut sorted = v.clone();
+                merge_sort(&mut sorted, cutoff);
+                assert_eq!(sorted, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_sort_is_stable() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Item(i64, usize);
+        impl PartialOrd for Item {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Item {
+            // compares by key only, the index tells equal keys apart
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+
+        let v: Vec<_> = random_vec(5000, 10)
+            .into_iter()
+            .enumerate()
+            .map(|(i, k)| Item(k, i))
+            .collect();
+        let mut expected = v.clone();
+        expected.sort();
+        let mut sorted = v;
+        merge_sort(&mut sorted, Cutoff::Fixed(3));
+        let idx = |v: &[Item]| v.iter().map(|i| i.1).collect::<Vec<_>>();
+        assert_eq!(idx(&sorted), idx(&expected));
+    }
+
+    #[test]
+    fn test_quickselect_matches_sequential() {
+        let mut rng = thread_rng();
+        for len in [1, 2, 10, 1000, 20_000] {
+            let v = random_vec(len, 100);
+            let mut sorted = v.clone();
+            sorted.sort();
+            for cutoff in CUTOFFS {
+                for _ in 0..5 {
+                    let k = rng.gen_range(0..len);
+                    assert_eq!(quickselect(&v, k, cutoff), Some(sorted[k]));
+                }
+                assert_eq!(quickselect(&v, 0, cutoff), sorted.first().copied());
+                assert_eq!(quickselect(&v, len - 1, cutoff), sorted.last().copied());
+            }
+            assert_eq!(quickselect(&v, len, Cutoff::Auto), None);
+        }
+    }
+
+    #[test]
+    fn test_scan_matches_sequential() {
+        for len in [0, 1, 2, 3, 999, 10_000] {
+            let v = random_vec(len, 1000);
+            let expected: Vec<i64> = v
+                .iter()
+                .scan(0, |acc, x| {
+                    *acc += x;
+                    Some(*acc)
+                })
+                .collect();
+            for cutoff in CUTOFFS {
+                assert_eq!(prefix_sum(&v, cutoff), expected);
+            }
+        }
+
+        // not commutative, so the chunks have to be combined in order
+        let words: Vec<String> = "the quick brown fox".split(' ').map(String::from).collect();
+        assert_eq!(
+            scan(&words, |a, b| format!("{}{}", a, b), Cutoff::Fixed(1)),
+            vec!["the", "thequick", "thequickbrown", "thequickbrownfox"]
+        );
+    }
+}
@@ -0,0 +1,440 @@
+// Generic version of cb_4_concurrency::parallel_pipeline: a source, any number
+// of stages with their own worker pools and bounded crossbeam channels, and a sink.
+//
+//     let report = Pipeline::source(1..=100)
+//         .stage("parse", 2, 8, |x: u64| Ok::<_, String>(x * 2))
+//         .stage("format", 4, 16, |x| Ok(format!("<{}>", x)))
+//         .preserve_order(true)
+//         .sink(|s| {
+//             println!("{}", s);
+//             Ok(())
+//         })?;
+//
+// The first error cancels the whole pipeline: the source stops producing,
+// workers stop picking up messages and the error is returned from `sink`.
+
+use crossbeam_channel::{bounded, Receiver, Sender};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Capacity of the channel between the last stage and the sink
+const SINK_CAPACITY: usize = 16;
+
+/// Messages carry their sequence number, so that the sink can restore the order
+type Msg<T> = (u64, T);
+
+type Spawn<T, E> = Box<dyn FnOnce(&mut Context<E>, usize) -> Receiver<Msg<T>> + Send>;
+
+struct Shared<E> {
+    cancelled: AtomicBool,
+    error: Mutex<Option<E>>,
+}
+
+impl<E> Shared<E> {
+    fn fail(&self, e: E) {
+        let mut error = self.error.lock().unwrap();
+        if error.is_none() {
+            *error = Some(e);
+        }
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+struct Context<E> {
+    shared: Arc<Shared<E>>,
+    handles: Vec<JoinHandle<()>>,
+    stats: Vec<Arc<StageStats>>,
+}
+
+#[derive(Default)]
+struct StageStats {
+    name: String,
+    workers: usize,
+    capacity: usize,
+    items: AtomicU64,
+    busy_ns: AtomicU64,
+    depth_sum: AtomicU64,
+    max_depth: AtomicUsize,
+}
+
+/// What a stage did during the run
+#[derive(Debug, Clone)]
+pub struct StageMetrics {
+    pub name: String,
+    pub workers: usize,
+    pub capacity: usize,
+    pub items: u64,
+    /// time the workers spent inside the stage function, summed over workers
+    pub busy: Duration,
+    /// input queue length seen by the workers when they took a message
+    pub avg_queue_depth: f64,
+    pub max_queue_depth: usize,
+}
+
+impl StageMetrics {
+    /// Items per second over the wall time of the whole run
+    pub fn throughput(&self, elapsed: Duration) -> f64 {
+        self.items as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Fraction of the available worker time spent doing work
+    pub fn utilisation(&self, elapsed: Duration) -> f64 {
+        self.busy.as_secs_f64() / (elapsed.as_secs_f64() * self.workers as f64).max(f64::EPSILON)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineReport {
+    pub elapsed: Duration,
+    pub sink_items: u64,
+    pub stages: Vec<StageMetrics>,
+}
+
+impl fmt::Display for PipelineReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} items in {:.3}s",
+            self.sink_items,
+            self.elapsed.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "{:<16} {:>7} {:>8} {:>10} {:>12} {:>8} {:>9} {:>9}",
+            "stage", "workers", "capacity", "items", "items/s", "busy %", "avg queue", "max queue"
+        )?;
+        for s in &self.stages {
+            writeln!(
+                f,
+                "{:<16} {:>7} {:>8} {:>10} {:>12.1} {:>8.1} {:>9.2} {:>9}",
+                s.name,
+                s.workers,
+                s.capacity,
+                s.items,
+                s.throughput(self.elapsed),
+                100.0 * s.utilisation(self.elapsed),
+                s.avg_queue_depth,
+                s.max_queue_depth
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Pipeline<T, E> {
+    spawn: Spawn<T, E>,
+    preserve_order: bool,
+}
+
+impl<T, E> Pipeline<T, E>
+where
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    pub fn source<I>(items: I) -> Pipeline<T, E>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'static,
+    {
+        Pipeline::try_source(items.into_iter().map(Ok))
+    }
+
+    /// A source which can fail, e.g. reading records from a file
+    pub fn try_source<I>(items: I) -> Pipeline<T, E>
+    where
+        I: IntoIterator<Item = Result<T, E>>,
+        I::IntoIter: Send + 'static,
+    {
+        let items = items.into_iter();
+        let spawn = move |ctx: &mut Context<E>, capacity: usize| {
+            let (snd, rcv) = bounded(capacity);
+            let shared = ctx.shared.clone();
+            ctx.handles.push(thread::spawn(move || {
+                for (seq, item) in (0u64..).zip(items) {
+                    if shared.is_cancelled() {
+                        break;
+                    }
+                    match item {
+                        Ok(item) => {
+                            if snd.send((seq, item)).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            shared.fail(e);
+                            break;
+                        }
+                    }
+                }
+                // dropping `snd` closes the channel and ends the workers' loops
+            }));
+            rcv
+        };
+        Pipeline {
+            spawn: Box::new(spawn),
+            preserve_order: false,
+        }
+    }
+
+    /// Adds a stage of `workers` threads reading from a channel of `capacity` messages
+    pub fn stage<U, F>(self, name: &str, workers: usize, capacity: usize, f: F) -> Pipeline<U, E>
+    where
+        U: Send + 'static,
+        F: Fn(T) -> Result<U, E> + Send + Sync + 'static,
+    {
+        let upstream = self.spawn;
+        let name = name.to_string();
+        let workers = workers.max(1);
+        let capacity = capacity.max(1);
+
+        let spawn = move |ctx: &mut Context<E>, out_capacity: usize| {
+            let input = upstream(ctx, capacity);
+            let (snd, rcv) = bounded(out_capacity);
+            let f = Arc::new(f);
+            let stats = Arc::new(StageStats {
+                name,
+                workers,
+                capacity,
+                ..Default::default()
+            });
+            ctx.stats.push(stats.clone());
+
+            for _ in 0..workers {
+                let (input, snd) = (input.clone(), snd.clone());
+                let (f, stats, shared) = (f.clone(), stats.clone(), ctx.shared.clone());
+                ctx.handles.push(thread::spawn(move || {
+                    run_worker(&input, &snd, f.as_ref(), &stats, &shared)
+                }));
+            }
+            rcv
+        };
+        Pipeline {
+            spawn: Box::new(spawn),
+            preserve_order: self.preserve_order,
+        }
+    }
+
+    /// Deliver items to the sink in source order, at the cost of buffering
+    /// the ones that overtook slower items
+    pub fn preserve_order(mut self, preserve: bool) -> Self {
+        self.preserve_order = preserve;
+        self
+    }
+
+    /// Runs the pipeline, `sink` is called on the current thread
+    pub fn sink<F>(self, mut sink: F) -> Result<PipelineReport, E>
+    where
+        F: FnMut(T) -> Result<(), E>,
+    {
+        let start = Instant::now();
+        let mut ctx = Context {
+            shared: Arc::new(Shared {
+                cancelled: AtomicBool::new(false),
+                error: Mutex::new(None),
+            }),
+            handles: Vec::new(),
+            stats: Vec::new(),
+        };
+        let input = (self.spawn)(&mut ctx, SINK_CAPACITY);
+        let shared = ctx.shared.clone();
+
+        let mut sink_items = 0;
+        let mut deliver = |item: T| match sink(item) {
+            Ok(()) => {
+                sink_items += 1;
+                true
+            }
+            Err(e) => {
+                shared.fail(e);
+                false
+            }
+        };
+
+        let mut pending = BTreeMap::new();
+        let mut next_seq = 0;
+        for (seq, item) in input.iter() {
+            if shared.is_cancelled() {
+                break;
+            }
+            if !self.preserve_order {
+                if !deliver(item) {
+                    break;
+                }
+                continue;
+            }
+            pending.insert(seq, item);
+            while let Some(item) = pending.remove(&next_seq) {
+                next_seq += 1;
+                if !deliver(item) {
+                    break;
+                }
+            }
+        }
+        drop(input);
+
+        for handle in ctx.handles {
+            if let Err(panic) = handle.join() {
+                std::panic::resume_unwind(panic);
+            }
+        }
+
+        if let Some(e) = shared.error.lock().unwrap().take() {
+            return Err(e);
+        }
+
+        Ok(PipelineReport {
+            elapsed: start.elapsed(),
+            sink_items,
+            stages: ctx.stats.iter().map(|s| s.metrics()).collect(),
+        })
+    }
+}
+
+fn run_worker<T, U, E, F>(
+    input: &Receiver<Msg<T>>,
+    output: &Sender<Msg<U>>,
+    f: &F,
+    stats: &StageStats,
+    shared: &Shared<E>,
+) where
+    F: Fn(T) -> Result<U, E>,
+{
+    loop {
+        let depth = input.len();
+        let (seq, item) = match input.recv() {
+            Ok(msg) => msg,
+            Err(_) => break,
+        };
+        if shared.is_cancelled() {
+            break;
+        }
+        stats.depth_sum.fetch_add(depth as u64, Ordering::Relaxed);
+        stats.max_depth.fetch_max(depth, Ordering::Relaxed);
+
+        let started = Instant::now();
+        let res = f(item);
+        stats
+            .busy_ns
+            .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+
+        match res {
+            Ok(out) => {
+                stats.items.fetch_add(1, Ordering::Relaxed);
+                if output.send((seq, out)).is_err() {
+                    break;
+                }
+            }
+            Err(e) => {
+                shared.fail(e);
+                break;
+            }
+        }
+    }
+}
+
+impl StageStats {
+    fn metrics(&self) -> StageMetrics {
+        let items = self.items.load(Ordering::Relaxed);
+        StageMetrics {
+            name: self.name.clone(),
+            workers: self.workers,
+            capacity: self.capacity,
+            items,
+            busy: Duration::from_nanos(self.busy_ns.load(Ordering::Relaxed)),
+            avg_queue_depth: self.depth_sum.load(Ordering::Relaxed) as f64 / (items.max(1) as f64),
+            max_queue_depth: self.max_depth.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU64;
+
+    #[test]
+    fn test_pipeline_preserves_order() {
+        let mut out = Vec::new();
+        let report = Pipeline::source(0..200u64)
+            .stage("jitter", 4, 4, |x| {
+                // later items finish first
+                thread::sleep(Duration::from_micros((200 - x) * 10));
+                Ok::<_, String>(x * 2)
+            })
+            .stage("format", 3, 8, |x| Ok(format!("{}", x)))
+            .preserve_order(true)
+            .sink(|s| {
+                out.push(s);
+                Ok(())
+            })
+            .unwrap();
+
+        let expected: Vec<_> = (0..200u64).map(|x| format!("{}", x * 2)).collect();
+        assert_eq!(out, expected);
+        assert_eq!(report.sink_items, 200);
+        assert_eq!(report.stages.len(), 2);
+        assert_eq!(report.stages[0].name, "jitter");
+        assert_eq!(report.stages[0].items, 200);
+        assert_eq!(report.stages[1].workers, 3);
+        assert!(report.stages[0].max_queue_depth <= 4);
+    }
+
+    #[test]
+    fn test_pipeline_unordered_delivers_everything() {
+        let mut sum = 0;
+        Pipeline::source(1..=1000u64)
+            .stage("square", 8, 2, |x| Ok::<_, ()>(x * x))
+            .sink(|x| {
+                sum += x;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(sum, (1..=1000u64).map(|x| x * x).sum::<u64>());
+    }
+
+    #[test]
+    fn test_pipeline_error_cancels_upstream() {
+        let produced = Arc::new(AtomicU64::new(0));
+        let counter = produced.clone();
+        let source = (0..).inspect(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let res = Pipeline::source(source)
+            .stage("check", 2, 2, |x: u64| {
+                if x == 10 {
+                    Err(format!("bad record {}", x))
+                } else {
+                    Ok(x)
+                }
+            })
+            .stage("pass", 2, 2, Ok)
+            .sink(|_| Ok(()));
+
+        assert_eq!(res.unwrap_err(), "bad record 10");
+        // the infinite source was stopped shortly after the failure
+        assert!(produced.load(Ordering::SeqCst) < 100);
+    }
+
+    #[test]
+    fn test_pipeline_source_and_sink_errors() {
+        let records = vec![Ok(1), Ok(2), Err("unreadable record"), Ok(4)];
+        let res = Pipeline::try_source(records)
+            .stage("id", 1, 1, Ok)
+            .sink(|_: i32| Ok(()));
+        assert_eq!(res.unwrap_err(), "unreadable record");
+
+        let res = Pipeline::source(0..100)
+            .stage("id", 2, 2, Ok)
+            .sink(|x: i32| if x < 5 { Ok(()) } else { Err("sink full") });
+        assert_eq!(res.unwrap_err(), "sink full");
+    }
+}
@@ -79,6 +79,601 @@ fn salt_hash_password() -> Result<(), Unspecified> {
     Ok(())
 }
 
+// A new file only its owner can read, fails if `path` already exists
+fn create_secret_file(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// Detached HMAC signatures of files, `sign_message` with a key kept in a file
+pub mod detached_hmac {
+    use data_encoding::HEXLOWER;
+    use ring::rand::{SecureRandom, SystemRandom};
+    use ring::{constant_time, hmac};
+    use std::fmt;
+    use std::fs::{self, File};
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum HmacAlgo {
+        Sha256,
+        Sha384,
+        Sha512,
+    }
+
+    impl HmacAlgo {
+        pub const ALL: [HmacAlgo; 3] = [HmacAlgo::Sha256, HmacAlgo::Sha384, HmacAlgo::Sha512];
+
+        pub fn name(&self) -> &'static str {
+            match self {
+                HmacAlgo::Sha256 => "hmac-sha256",
+                HmacAlgo::Sha384 => "hmac-sha384",
+                HmacAlgo::Sha512 => "hmac-sha512",
+            }
+        }
+
+        fn algorithm(&self) -> hmac::Algorithm {
+            match self {
+                HmacAlgo::Sha256 => hmac::HMAC_SHA256,
+                HmacAlgo::Sha384 => hmac::HMAC_SHA384,
+                HmacAlgo::Sha512 => hmac::HMAC_SHA512,
+            }
+        }
+    }
+
+    impl fmt::Display for HmacAlgo {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.name())
+        }
+    }
+
+    impl FromStr for HmacAlgo {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            // accepts both "hmac-sha256" and "sha256"
+            HmacAlgo::ALL
+                .iter()
+                .find(|a| a.name().eq_ignore_ascii_case(s) || a.name()[5..].eq_ignore_ascii_case(s))
+                .copied()
+                .ok_or_else(|| format!("unknown HMAC algorithm '{}'", s))
+        }
+    }
+
+    fn invalid_data(msg: String) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    }
+
+    /// Writes 64 random bytes as hex, enough for every algorithm.
+    /// The file must not exist yet and only its owner can read it.
+    pub fn generate_key(path: &Path) -> io::Result<()> {
+        let mut key = [0u8; 64];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| io::Error::other("no system randomness"))?;
+
+        let mut file = super::create_secret_file(path)?;
+        writeln!(file, "{}", HEXLOWER.encode(&key))
+    }
+
+    pub fn read_key(path: &Path) -> io::Result<Vec<u8>> {
+        let text = fs::read_to_string(path)?;
+        HEXLOWER
+            .decode(text.trim().as_bytes())
+            .map_err(|e| invalid_data(format!("{}: {}", path.display(), e)))
+    }
+
+    /// `FILE.sig`
+    pub fn signature_path(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".sig");
+        name.into()
+    }
+
+    /// HMAC of everything `input` yields, read in 64 KiB blocks
+    pub fn sign_stream<R: Read>(key: &[u8], algo: HmacAlgo, mut input: R) -> io::Result<hmac::Tag> {
+        let key = hmac::Key::new(algo.algorithm(), key);
+        let mut ctx = hmac::Context::with_key(&key);
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let count = input.read(&mut buffer)?;
+            if count == 0 {
+                break;
+            }
+            ctx.update(&buffer[..count]);
+        }
+        Ok(ctx.sign())
+    }
+
+    /// Writes `<algo> <hex>` into `FILE.sig` and returns its path
+    pub fn sign_file(key: &[u8], algo: HmacAlgo, path: &Path) -> io::Result<PathBuf> {
+        let tag = sign_stream(key, algo, File::open(path)?)?;
+        let sig_path = signature_path(path);
+        fs::write(
+            &sig_path,
+            format!("{} {}\n", algo, HEXLOWER.encode(tag.as_ref())),
+        )?;
+        Ok(sig_path)
+    }
+
+    /// `Ok(false)` when the file or the signature was changed or the key is wrong
+    pub fn verify_file(key: &[u8], path: &Path, sig_path: &Path) -> io::Result<bool> {
+        let mut line = String::new();
+        BufReader::new(File::open(sig_path)?).read_line(&mut line)?;
+        let (algo, hex) = line
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| invalid_data(format!("{}: malformed signature", sig_path.display())))?;
+        let algo = algo.parse::<HmacAlgo>().map_err(invalid_data)?;
+        let expected = HEXLOWER
+            .decode(hex.as_bytes())
+            .map_err(|e| invalid_data(format!("{}: {}", sig_path.display(), e)))?;
+
+        let tag = sign_stream(key, algo, File::open(path)?)?;
+        // hmac::verify needs the whole message in memory, so the streamed tag
+        // is compared the way it does it, in constant time
+        Ok(constant_time::verify_slices_are_equal(tag.as_ref(), &expected).is_ok())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_sign_and_verify_file() {
+            let dir = tempfile::tempdir().unwrap();
+            let key_path = dir.path().join("key");
+            generate_key(&key_path).unwrap();
+            // never overwrites an existing key
+            assert!(generate_key(&key_path).is_err());
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = fs::metadata(&key_path).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o600);
+            }
+            let key = read_key(&key_path).unwrap();
+
+            let file = dir.path().join("data.bin");
+            fs::write(&file, vec![1u8; 200_000]).unwrap();
+            for algo in HmacAlgo::ALL {
+                let sig = sign_file(&key, algo, &file).unwrap();
+                assert_eq!(sig, dir.path().join("data.bin.sig"));
+                assert!(verify_file(&key, &file, &sig).unwrap());
+                assert!(!verify_file(b"other key", &file, &sig).unwrap());
+            }
+
+            // streaming gives the same tag as signing in one go
+            let one_shot = hmac::sign(
+                &hmac::Key::new(hmac::HMAC_SHA512, &key),
+                &fs::read(&file).unwrap(),
+            );
+            let sig = fs::read_to_string(signature_path(&file)).unwrap();
+            assert_eq!(
+                sig,
+                format!("hmac-sha512 {}\n", HEXLOWER.encode(one_shot.as_ref()))
+            );
+
+            fs::write(&file, vec![2u8; 200_000]).unwrap();
+            assert!(!verify_file(&key, &file, &signature_path(&file)).unwrap());
+        }
+    }
+}
+
+/// Ed25519 signatures of files. The secret key is kept as PKCS#8, public keys
+/// are exchanged as hex or base64 and signatures name the key that made them.
+pub mod ed25519 {
+    use base64::{engine::general_purpose, Engine as _};
+    use data_encoding::HEXLOWER;
+    use ring::digest::{self, SHA256, SHA512};
+    use ring::rand::SystemRandom;
+    use ring::signature::{self, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+    use std::fs::{self, File};
+    use std::io::{self, BufReader, Read, Write};
+    use std::path::{Path, PathBuf};
+
+    /// Tag of the signature files, Ed25519 over the SHA-512 digest of the file
+    const SCHEME: &str = "ed25519-sha512";
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum KeyFormat {
+        Hex,
+        Base64,
+    }
+
+    impl std::str::FromStr for KeyFormat {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "hex" => Ok(KeyFormat::Hex),
+                "base64" => Ok(KeyFormat::Base64),
+                _ => Err(format!("unknown key format '{}'", s)),
+            }
+        }
+    }
+
+    fn invalid_data(msg: String) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    }
+
+    /// Writes the PKCS#8 key pair to `path`, readable only by its owner, and
+    /// the hex public key to `path.pub`. Returns the path of the public key.
+    pub fn generate_keypair(path: &Path) -> io::Result<PathBuf> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| io::Error::other("could not generate the key pair"))?;
+        super::create_secret_file(path)?.write_all(pkcs8.as_ref())?;
+
+        let keypair = load_keypair(path)?;
+        let mut pub_path = path.as_os_str().to_owned();
+        pub_path.push(".pub");
+        let pub_path = PathBuf::from(pub_path);
+        fs::write(
+            &pub_path,
+            export_public_key(&keypair, KeyFormat::Hex) + "\n",
+        )?;
+        Ok(pub_path)
+    }
+
+    pub fn load_keypair(path: &Path) -> io::Result<Ed25519KeyPair> {
+        Ed25519KeyPair::from_pkcs8(&fs::read(path)?)
+            .map_err(|e| invalid_data(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn export_public_key(keypair: &Ed25519KeyPair, format: KeyFormat) -> String {
+        let key = keypair.public_key().as_ref();
+        match format {
+            KeyFormat::Hex => HEXLOWER.encode(key),
+            KeyFormat::Base64 => general_purpose::STANDARD.encode(key),
+        }
+    }
+
+    /// Accepts both export formats
+    pub fn parse_public_key(text: &str) -> Option<Vec<u8>> {
+        let text = text.trim();
+        HEXLOWER
+            .decode(text.to_ascii_lowercase().as_bytes())
+            .ok()
+            .or_else(|| general_purpose::STANDARD.decode(text).ok())
+            .filter(|key| key.len() == 32)
+    }
+
+    /// First 8 bytes of the SHA-256 of the public key, in hex
+    pub fn fingerprint(public_key: &[u8]) -> String {
+        HEXLOWER.encode(&digest::digest(&SHA256, public_key).as_ref()[..8])
+    }
+
+    fn file_digest(path: &Path) -> io::Result<digest::Digest> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut ctx = digest::Context::new(&SHA512);
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let count = reader.read(&mut buffer)?;
+            if count == 0 {
+                break;
+            }
+            ctx.update(&buffer[..count]);
+        }
+        Ok(ctx.finish())
+    }
+
+    /// Writes `ed25519-sha512 <fingerprint> <base64 signature>` into `sig_path`.
+    /// The file is hashed first, so it never has to fit in memory.
+    pub fn sign_file(keypair: &Ed25519KeyPair, path: &Path, sig_path: &Path) -> io::Result<()> {
+        let sig = keypair.sign(file_digest(path)?.as_ref());
+        fs::write(
+            sig_path,
+            format!(
+                "{} {} {}\n",
+                SCHEME,
+                fingerprint(keypair.public_key().as_ref()),
+                general_purpose::STANDARD.encode(sig.as_ref())
+            ),
+        )
+    }
+
+    /// Looks up the key named by the signature among the public key files in
+    /// `keyring` and checks the signature with it. Returns the key file when
+    /// the signature is good, `None` when it is not.
+    pub fn verify_file(
+        keyring: &Path,
+        path: &Path,
+        sig_path: &Path,
+    ) -> io::Result<Option<PathBuf>> {
+        let text = fs::read_to_string(sig_path)?;
+        let malformed = || invalid_data(format!("{}: malformed signature", sig_path.display()));
+        let mut fields = text.split_whitespace();
+        if fields.next() != Some(SCHEME) {
+            return Err(malformed());
+        }
+        let wanted = fields.next().ok_or_else(malformed)?;
+        let sig = fields
+            .next()
+            .and_then(|s| general_purpose::STANDARD.decode(s).ok())
+            .ok_or_else(malformed)?;
+
+        let (key_path, key) = find_key(keyring, wanted)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no key with fingerprint {} in {}",
+                    wanted,
+                    keyring.display()
+                ),
+            )
+        })?;
+        let digest = file_digest(path)?;
+        let ok = UnparsedPublicKey::new(&signature::ED25519, key)
+            .verify(digest.as_ref(), &sig)
+            .is_ok();
+        Ok(ok.then_some(key_path))
+    }
+
+    // files that don't hold a public key, like the PKCS#8 secret keys, are skipped
+    fn find_key(keyring: &Path, wanted: &str) -> io::Result<Option<(PathBuf, Vec<u8>)>> {
+        for entry in fs::read_dir(keyring)? {
+            let path = entry?.path();
+            let key = fs::read_to_string(&path)
+                .ok()
+                .and_then(|t| parse_public_key(&t));
+            if let Some(key) = key.filter(|k| fingerprint(k) == wanted) {
+                return Ok(Some((path, key)));
+            }
+        }
+        Ok(None)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_sign_and_verify_with_keyring() {
+            let dir = tempfile::tempdir().unwrap();
+            let keyring = dir.path().join("keyring");
+            fs::create_dir(&keyring).unwrap();
+
+            let alice = keyring.join("alice");
+            let alice_pub = generate_keypair(&alice).unwrap();
+            assert!(generate_keypair(&alice).is_err());
+            // the keyring holds bob's key as base64
+            let bob = dir.path().join("bob");
+            generate_keypair(&bob).unwrap();
+            let bob_pair = load_keypair(&bob).unwrap();
+            let bob_b64 = export_public_key(&bob_pair, KeyFormat::Base64);
+            fs::write(keyring.join("bob.pub"), &bob_b64).unwrap();
+            assert_eq!(
+                parse_public_key(&bob_b64),
+                Some(bob_pair.public_key().as_ref().to_vec())
+            );
+
+            let file = dir.path().join("release.tar.gz");
+            fs::write(&file, vec![3u8; 100_000]).unwrap();
+            let sig = dir.path().join("release.tar.gz.sig");
+
+            sign_file(&load_keypair(&alice).unwrap(), &file, &sig).unwrap();
+            assert_eq!(verify_file(&keyring, &file, &sig).unwrap(), Some(alice_pub));
+
+            sign_file(&bob_pair, &file, &sig).unwrap();
+            assert_eq!(
+                verify_file(&keyring, &file, &sig).unwrap(),
+                Some(keyring.join("bob.pub"))
+            );
+
+            fs::write(&file, vec![4u8; 100_000]).unwrap();
+            assert_eq!(verify_file(&keyring, &file, &sig).unwrap(), None);
+
+            let stranger = dir.path().join("stranger");
+            generate_keypair(&stranger).unwrap();
+            sign_file(&load_keypair(&stranger).unwrap(), &file, &sig).unwrap();
+            let err = verify_file(&keyring, &file, &sig).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        }
+    }
+}
+
+/// Password hashes stored as PHC strings, `$pbkdf2-sha512$i=<iterations>$<salt>$<hash>`
+/// with salt and hash in unpadded base64, so the parameters travel with the hash
+pub mod password {
+    use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine as _};
+    use ring::error::Unspecified;
+    use ring::pbkdf2;
+    use ring::rand::{SecureRandom, SystemRandom};
+    use std::fmt;
+    use std::num::NonZeroU32;
+    use std::str::FromStr;
+
+    const ALGORITHM_ID: &str = "pbkdf2-sha512";
+
+    /// Parameters new hashes are made with, older hashes below them should be rehashed
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Policy {
+        pub iterations: NonZeroU32,
+        pub salt_len: usize,
+        pub hash_len: usize,
+    }
+
+    impl Default for Policy {
+        fn default() -> Self {
+            Policy {
+                // OWASP recommendation for PBKDF2-HMAC-SHA512
+                iterations: NonZeroU32::new(210_000).unwrap(),
+                salt_len: 16,
+                hash_len: 64,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct PhcHash {
+        pub iterations: NonZeroU32,
+        pub salt: Vec<u8>,
+        pub hash: Vec<u8>,
+    }
+
+    impl PhcHash {
+        pub fn is_weaker_than(&self, policy: &Policy) -> bool {
+            self.iterations < policy.iterations
+                || self.salt.len() < policy.salt_len
+                || self.hash.len() < policy.hash_len
+        }
+    }
+
+    impl fmt::Display for PhcHash {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "${}$i={}${}${}",
+                ALGORITHM_ID,
+                self.iterations,
+                STANDARD_NO_PAD.encode(&self.salt),
+                STANDARD_NO_PAD.encode(&self.hash)
+            )
+        }
+    }
+
+    impl FromStr for PhcHash {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let fields: Vec<&str> = s.split('$').collect();
+            let [empty, id, params, salt, hash] = fields[..] else {
+                return Err(format!("expected 5 '$' separated fields in '{}'", s));
+            };
+            if !empty.is_empty() || id != ALGORITHM_ID {
+                return Err(format!("not a {} hash", ALGORITHM_ID));
+            }
+            let iterations = params
+                .strip_prefix("i=")
+                .and_then(|i| i.parse().ok())
+                .ok_or_else(|| format!("bad parameters '{}'", params))?;
+            let decode = |field: &str| {
+                STANDARD_NO_PAD
+                    .decode(field)
+                    .map_err(|e| format!("bad base64 '{}': {}", field, e))
+            };
+            Ok(PhcHash {
+                iterations,
+                salt: decode(salt)?,
+                hash: decode(hash)?,
+            })
+        }
+    }
+
+    /// A PHC string for `password` with a fresh random salt
+    pub fn hash_password(password: &str, policy: &Policy) -> Result<String, Unspecified> {
+        let mut salt = vec![0u8; policy.salt_len];
+        SystemRandom::new().fill(&mut salt)?;
+        let mut hash = vec![0u8; policy.hash_len];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA512,
+            policy.iterations,
+            &salt,
+            password.as_bytes(),
+            &mut hash,
+        );
+        Ok(PhcHash {
+            iterations: policy.iterations,
+            salt,
+            hash,
+        }
+        .to_string())
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Verification {
+        Invalid,
+        /// `needs_rehash` when the stored hash is weaker than the policy,
+        /// the caller should store a new hash of the password it just checked
+        Valid {
+            needs_rehash: bool,
+        },
+    }
+
+    pub fn verify_password(
+        password: &str,
+        stored: &str,
+        policy: &Policy,
+    ) -> Result<Verification, String> {
+        let phc: PhcHash = stored.parse()?;
+        // pbkdf2::verify compares in constant time
+        let res = pbkdf2::verify(
+            pbkdf2::PBKDF2_HMAC_SHA512,
+            phc.iterations,
+            &phc.salt,
+            password.as_bytes(),
+            &phc.hash,
+        );
+        Ok(match res {
+            Ok(_) => Verification::Valid {
+                needs_rehash: phc.is_weaker_than(policy),
+            },
+            Err(_) => Verification::Invalid,
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn policy(iterations: u32) -> Policy {
+            Policy {
+                iterations: NonZeroU32::new(iterations).unwrap(),
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn test_hash_and_verify() {
+            let stored = hash_password("hunter2", &policy(1000)).unwrap();
+            assert!(stored.starts_with("$pbkdf2-sha512$i=1000$"));
+            let phc: PhcHash = stored.parse().unwrap();
+            assert_eq!((phc.salt.len(), phc.hash.len()), (16, 64));
+            assert_eq!(phc.to_string(), stored);
+
+            assert_eq!(
+                verify_password("hunter2", &stored, &policy(1000)),
+                Ok(Verification::Valid {
+                    needs_rehash: false
+                })
+            );
+            assert_eq!(
+                verify_password("hunter3", &stored, &policy(1000)),
+                Ok(Verification::Invalid)
+            );
+            assert_eq!(
+                verify_password("hunter2", &stored, &policy(2000)),
+                Ok(Verification::Valid { needs_rehash: true })
+            );
+            let longer_salt = Policy {
+                salt_len: 32,
+                ..policy(1000)
+            };
+            assert!(phc.is_weaker_than(&longer_salt));
+        }
+
+        #[test]
+        fn test_parse_errors() {
+            assert!("$pbkdf2-sha256$i=1$AAAA$AAAA".parse::<PhcHash>().is_err());
+            assert!("$pbkdf2-sha512$i=0$AAAA$AAAA".parse::<PhcHash>().is_err());
+            assert!("$pbkdf2-sha512$rounds=1$AAAA$AAAA"
+                .parse::<PhcHash>()
+                .is_err());
+            assert!("$pbkdf2-sha512$i=1$AAAA".parse::<PhcHash>().is_err());
+            assert!("$pbkdf2-sha512$i=1$!!$AAAA".parse::<PhcHash>().is_err());
+            assert!(verify_password("x", "plain text", &Policy::default()).is_err());
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,385 @@
+// One-time passwords for two-factor authentication: HOTP (RFC 4226), TOTP
+// (RFC 6238) and the otpauth:// URIs authenticator apps read from QR codes.
+
+use data_encoding::BASE32_NOPAD;
+use percent_encoding::percent_decode_str;
+use ring::{constant_time, hmac};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpAlgo {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl OtpAlgo {
+    pub const ALL: [OtpAlgo; 3] = [OtpAlgo::Sha1, OtpAlgo::Sha256, OtpAlgo::Sha512];
+
+    /// Spelling used by otpauth URIs
+    pub fn name(&self) -> &'static str {
+        match self {
+            OtpAlgo::Sha1 => "SHA1",
+            OtpAlgo::Sha256 => "SHA256",
+            OtpAlgo::Sha512 => "SHA512",
+        }
+    }
+
+    fn algorithm(&self) -> hmac::Algorithm {
+        match self {
+            // HOTP is defined on top of SHA-1, which is still fine inside HMAC
+            OtpAlgo::Sha1 => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+            OtpAlgo::Sha256 => hmac::HMAC_SHA256,
+            OtpAlgo::Sha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+impl fmt::Display for OtpAlgo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for OtpAlgo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OtpAlgo::ALL
+            .iter()
+            .find(|a| a.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown OTP algorithm '{}'", s))
+    }
+}
+
+/// RFC 4226 HOTP value of `counter`, `digits` long and zero padded
+pub fn hotp(secret: &[u8], counter: u64, digits: u32, algo: OtpAlgo) -> String {
+    let key = hmac::Key::new(algo.algorithm(), secret);
+    let mac = hmac::sign(&key, &counter.to_be_bytes());
+    let mac = mac.as_ref();
+
+    // dynamic truncation: the low nibble of the last byte picks 4 bytes
+    let offset = (mac[mac.len() - 1] & 0x0f) as usize;
+    let bin = u32::from_be_bytes([
+        mac[offset],
+        mac[offset + 1],
+        mac[offset + 2],
+        mac[offset + 3],
+    ]) & 0x7fff_ffff;
+    format!(
+        "{:0width$}",
+        bin as u64 % 10u64.pow(digits),
+        width = digits as usize
+    )
+}
+
+/// Secrets are base32, often shown in lower case groups of four, with or without padding
+pub fn decode_secret(text: &str) -> Result<Vec<u8>, String> {
+    let cleaned: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    BASE32_NOPAD
+        .decode(cleaned.as_bytes())
+        .map_err(|e| format!("invalid base32 secret: {}", e))
+}
+
+pub fn encode_secret(secret: &[u8]) -> String {
+    BASE32_NOPAD.encode(secret)
+}
+
+fn codes_equal(a: &str, b: &str) -> bool {
+    constant_time::verify_slices_are_equal(a.as_bytes(), b.as_bytes()).is_ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpKind {
+    /// counter based, the counter moves on with every code used
+    Hotp { counter: u64 },
+    /// time based, a new code every `period` seconds
+    Totp { period: u64 },
+}
+
+/// Everything an otpauth:// URI describes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtpAuth {
+    pub kind: OtpKind,
+    pub secret: Vec<u8>,
+    pub algo: OtpAlgo,
+    pub digits: u32,
+    pub issuer: Option<String>,
+    pub account: String,
+}
+
+impl OtpAuth {
+    /// TOTP with the defaults every authenticator app supports
+    pub fn totp(secret: Vec<u8>, account: &str) -> OtpAuth {
+        OtpAuth {
+            kind: OtpKind::Totp { period: 30 },
+            secret,
+            algo: OtpAlgo::Sha1,
+            digits: 6,
+            issuer: None,
+            account: account.to_string(),
+        }
+    }
+
+    /// Code for the current counter, or for the time step containing `unix_time`
+    pub fn code_at(&self, unix_time: u64) -> String {
+        hotp(
+            &self.secret,
+            self.counter_at(unix_time),
+            self.digits,
+            self.algo,
+        )
+    }
+
+    pub fn code(&self) -> String {
+        self.code_at(now())
+    }
+
+    fn counter_at(&self, unix_time: u64) -> u64 {
+        match self.kind {
+            OtpKind::Hotp { counter } => counter,
+            OtpKind::Totp { period } => unix_time / period,
+        }
+    }
+
+    /// Accepts codes up to `window` steps away from the expected one: clock
+    /// skew in both directions for TOTP, skipped codes ahead for HOTP.
+    /// Returns the counter the code matched, a HOTP caller continues after
+    /// it and a TOTP caller can refuse a counter it has seen before.
+    pub fn verify_at(&self, code: &str, unix_time: u64, window: u64) -> Option<u64> {
+        let expected = self.counter_at(unix_time);
+        let lo = match self.kind {
+            OtpKind::Hotp { .. } => expected,
+            OtpKind::Totp { .. } => expected.saturating_sub(window),
+        };
+        let candidates = lo..=expected.saturating_add(window);
+        // no early exit, so the timing doesn't reveal which step matched
+        candidates.fold(None, |found, counter| {
+            let code_matches =
+                codes_equal(&hotp(&self.secret, counter, self.digits, self.algo), code);
+            found.or(code_matches.then_some(counter))
+        })
+    }
+
+    pub fn verify(&self, code: &str, window: u64) -> Option<u64> {
+        self.verify_at(code, now(), window)
+    }
+
+    pub fn to_uri(&self) -> String {
+        let (kind, extra) = match self.kind {
+            OtpKind::Hotp { counter } => ("hotp", ("counter", counter)),
+            OtpKind::Totp { period } => ("totp", ("period", period)),
+        };
+        let mut url = Url::parse(&format!("otpauth://{}/", kind)).expect("valid base URI");
+        let label = match &self.issuer {
+            Some(issuer) => format!("{}:{}", issuer, self.account),
+            None => self.account.clone(),
+        };
+        url.set_path(&label);
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("secret", &encode_secret(&self.secret));
+            if let Some(issuer) = &self.issuer {
+                query.append_pair("issuer", issuer);
+            }
+            query
+                .append_pair("algorithm", self.algo.name())
+                .append_pair("digits", &self.digits.to_string())
+                .append_pair(extra.0, &extra.1.to_string());
+        }
+        url.to_string()
+    }
+}
+
+impl FromStr for OtpAuth {
+    type Err = String;
+
+    /// Parses `otpauth://TYPE/[ISSUER:]ACCOUNT?secret=...`, the parameters
+    /// other than the secret are optional
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let url = Url::parse(s).map_err(|e| e.to_string())?;
+        if url.scheme() != "otpauth" {
+            return Err(format!("not an otpauth URI: '{}'", s));
+        }
+        let label = percent_decode_str(url.path().trim_start_matches('/'))
+            .decode_utf8()
+            .map_err(|e| e.to_string())?;
+        let (mut issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.to_string()), account.trim().to_string()),
+            None => (None, label.to_string()),
+        };
+
+        let mut secret = None;
+        let (mut algo, mut digits, mut counter, mut period) = (OtpAlgo::Sha1, 6, None, 30);
+        let number = |key: &str, value: &str| {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("bad {} '{}'", key, value))
+        };
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "secret" => secret = Some(decode_secret(&value)?),
+                // the parameter wins over the label prefix
+                "issuer" => issuer = Some(value.to_string()),
+                "algorithm" => algo = value.parse()?,
+                "digits" => {
+                    digits = u32::try_from(number(&key, &value)?)
+                        .map_err(|_| format!("bad digits '{}'", value))?
+                }
+                "counter" => counter = Some(number(&key, &value)?),
+                "period" => period = number(&key, &value)?,
+                _ => {}
+            }
+        }
+        if !(6..=10).contains(&digits) || period == 0 {
+            return Err(format!(
+                "unsupported digits {} or period {}",
+                digits, period
+            ));
+        }
+
+        let kind = match url.host_str() {
+            Some("totp") => OtpKind::Totp { period },
+            Some("hotp") => OtpKind::Hotp {
+                counter: counter.ok_or("a hotp URI needs a counter")?,
+            },
+            other => return Err(format!("unknown OTP type {:?}", other)),
+        };
+        Ok(OtpAuth {
+            kind,
+            secret: secret.ok_or("the URI has no secret")?,
+            algo,
+            digits,
+            issuer,
+            account,
+        })
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Clock before 1970")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RFC4226_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_hotp_rfc4226_vectors() {
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(
+                &hotp(RFC4226_SECRET, counter as u64, 6, OtpAlgo::Sha1),
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn test_totp_rfc6238_vectors() {
+        let seeds = [
+            (OtpAlgo::Sha1, b"12345678901234567890".to_vec()),
+            (
+                OtpAlgo::Sha256,
+                b"12345678901234567890123456789012".to_vec(),
+            ),
+            (
+                OtpAlgo::Sha512,
+                b"1234567890"
+                    .repeat(6)
+                    .into_iter()
+                    .chain(*b"1234")
+                    .collect(),
+            ),
+        ];
+        let vectors: [(u64, [&str; 3]); 6] = [
+            (59, ["94287082", "46119246", "90693936"]),
+            (1111111109, ["07081804", "68084774", "25091201"]),
+            (1111111111, ["14050471", "67062674", "99943326"]),
+            (1234567890, ["89005924", "91819424", "93441116"]),
+            (2000000000, ["69279037", "90698825", "38618901"]),
+            (20000000000, ["65353130", "77737706", "47863826"]),
+        ];
+        for (time, codes) in vectors {
+            for ((algo, seed), code) in seeds.iter().zip(codes) {
+                let otp = OtpAuth {
+                    algo: *algo,
+                    digits: 8,
+                    ..OtpAuth::totp(seed.clone(), "test")
+                };
+                assert_eq!(otp.code_at(time), code, "{} at {}", algo, time);
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_window() {
+        let otp = OtpAuth::totp(RFC4226_SECRET.to_vec(), "alice");
+        let t = 1_000_000_000;
+        let previous = otp.code_at(t - 30);
+        assert_eq!(otp.verify_at(&otp.code_at(t), t, 0), Some(t / 30));
+        assert_eq!(otp.verify_at(&previous, t, 0), None);
+        assert_eq!(otp.verify_at(&previous, t, 1), Some(t / 30 - 1));
+        assert_eq!(otp.verify_at(&otp.code_at(t + 60), t, 1), None);
+        assert_eq!(otp.verify_at("000000x", t, 1), None);
+
+        let hotp = OtpAuth {
+            kind: OtpKind::Hotp { counter: 3 },
+            ..otp
+        };
+        assert_eq!(hotp.verify_at("338314", 0, 2), Some(4));
+        // HOTP only looks ahead
+        assert_eq!(hotp.verify_at("359152", 0, 2), None);
+    }
+
+    #[test]
+    fn test_uri_round_trip() {
+        let uri = "otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60";
+        let otp: OtpAuth = uri.parse().unwrap();
+        assert_eq!(otp.kind, OtpKind::Totp { period: 60 });
+        assert_eq!(otp.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(otp.account, "john.doe@email.com");
+        assert_eq!((otp.algo, otp.digits), (OtpAlgo::Sha256, 8));
+        assert_eq!(
+            encode_secret(&otp.secret),
+            "HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ"
+        );
+        assert_eq!(otp.to_uri().parse::<OtpAuth>().unwrap(), otp);
+
+        let hotp: OtpAuth = "otpauth://hotp/bob?secret=gezd gnbv gy3t qojq&counter=7"
+            .replace(' ', "%20")
+            .parse()
+            .unwrap();
+        assert_eq!(hotp.kind, OtpKind::Hotp { counter: 7 });
+        assert_eq!((hotp.issuer, hotp.account.as_str()), (None, "bob"));
+        assert_eq!(hotp.secret, b"1234567890");
+
+        assert!("otpauth://hotp/bob?secret=GEZDGNBV"
+            .parse::<OtpAuth>()
+            .is_err());
+        assert!("otpauth://totp/bob".parse::<OtpAuth>().is_err());
+        assert!("https://example.com/?secret=GEZDGNBV"
+            .parse::<OtpAuth>()
+            .is_err());
+        // 2^32 + 6 would wrap around to 6
+        assert!("otpauth://totp/x?secret=GEZDGNBV&digits=4294967302"
+            .parse::<OtpAuth>()
+            .is_err());
+    }
+}
@@ -0,0 +1,508 @@
+// Passphrase based file encryption, streamed in fixed-size chunks.
+//
+// File layout, all integers big endian:
+//   magic "PGVT" | version u8 | cipher u8 | iterations u32 | chunk size u32
+//   | salt [16] | nonce prefix [7] | chunks...
+// Every chunk is sealed separately with a nonce made of the prefix, the chunk
+// counter (u32) and a flag set only on the last chunk, and with the header as
+// associated data. So a modified header, a reordered or modified chunk, a
+// dropped tail or appended data all fail authentication.
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, CHACHA20_POLY1305};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::str::FromStr;
+
+const MAGIC: &[u8; 4] = b"PGVT";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_PREFIX_LEN: usize = 7;
+const HEADER_LEN: usize = 4 + 1 + 1 + 4 + 4 + SALT_LEN + NONCE_PREFIX_LEN;
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+// rejects headers which would make us allocate silly amounts of memory
+const MAX_CHUNK_SIZE: usize = 16 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cipher {
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl Cipher {
+    pub const ALL: [Cipher; 2] = [Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Cipher::Aes256Gcm => "aes256gcm",
+            Cipher::ChaCha20Poly1305 => "chacha20poly1305",
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Cipher::Aes256Gcm => 1,
+            Cipher::ChaCha20Poly1305 => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Cipher> {
+        Cipher::ALL.iter().find(|c| c.id() == id).copied()
+    }
+
+    fn key(&self, key: &[u8; KEY_LEN]) -> LessSafeKey {
+        let algorithm = match self {
+            Cipher::Aes256Gcm => &AES_256_GCM,
+            Cipher::ChaCha20Poly1305 => &CHACHA20_POLY1305,
+        };
+        LessSafeKey::new(UnboundKey::new(algorithm, key).expect("Key length matches the cipher"))
+    }
+}
+
+impl fmt::Display for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Cipher {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Cipher::ALL
+            .iter()
+            .find(|c| c.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown cipher '{}'", s))
+    }
+}
+
+#[derive(Debug)]
+pub enum VaultError {
+    Io(io::Error),
+    NotAVault,
+    UnsupportedVersion(u8),
+    UnknownCipher(u8),
+    BadHeader(&'static str),
+    /// the file ends in the middle of the header or of a chunk tag
+    Truncated,
+    /// wrong passphrase, or the chunk was modified, moved or cut off
+    Authentication {
+        chunk: u32,
+    },
+    TooLarge,
+}
+
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VaultError::Io(e) => write!(f, "{}", e),
+            VaultError::NotAVault => write!(f, "not a vault file"),
+            VaultError::UnsupportedVersion(v) => write!(f, "unsupported vault version {}", v),
+            VaultError::UnknownCipher(id) => write!(f, "unknown cipher id {}", id),
+            VaultError::BadHeader(what) => write!(f, "invalid header: {}", what),
+            VaultError::Truncated => write!(f, "the file is truncated"),
+            VaultError::Authentication { chunk } => write!(
+                f,
+                "chunk {} failed authentication: wrong passphrase, or the file was modified",
+                chunk
+            ),
+            VaultError::TooLarge => write!(f, "too many chunks for one file"),
+        }
+    }
+}
+
+impl std::error::Error for VaultError {}
+
+impl From<io::Error> for VaultError {
+    fn from(e: io::Error) -> Self {
+        VaultError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VaultOptions {
+    pub cipher: Cipher,
+    /// PBKDF2-HMAC-SHA256 iterations, stored in the header
+    pub iterations: NonZeroU32,
+    pub chunk_size: usize,
+}
+
+impl Default for VaultOptions {
+    fn default() -> Self {
+        VaultOptions {
+            cipher: Cipher::Aes256Gcm,
+            iterations: NonZeroU32::new(600_000).unwrap(),
+            chunk_size: 64 * 1024,
+        }
+    }
+}
+
+struct Header {
+    cipher: Cipher,
+    iterations: NonZeroU32,
+    chunk_size: usize,
+    salt: [u8; SALT_LEN],
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+}
+
+impl Header {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN);
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        buf.push(self.cipher.id());
+        buf.write_u32::<BigEndian>(self.iterations.get()).unwrap();
+        buf.write_u32::<BigEndian>(self.chunk_size as u32).unwrap();
+        buf.extend_from_slice(&self.salt);
+        buf.extend_from_slice(&self.nonce_prefix);
+        buf
+    }
+
+    fn parse(mut bytes: &[u8]) -> Result<Header, VaultError> {
+        let mut magic = [0u8; 4];
+        bytes.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(VaultError::NotAVault);
+        }
+        let version = bytes.read_u8()?;
+        if version != VERSION {
+            return Err(VaultError::UnsupportedVersion(version));
+        }
+        let id = bytes.read_u8()?;
+        let cipher = Cipher::from_id(id).ok_or(VaultError::UnknownCipher(id))?;
+        let iterations = NonZeroU32::new(bytes.read_u32::<BigEndian>()?)
+            .ok_or(VaultError::BadHeader("zero iterations"))?;
+        let chunk_size = bytes.read_u32::<BigEndian>()? as usize;
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(VaultError::BadHeader("chunk size out of range"));
+        }
+        let mut header = Header {
+            cipher,
+            iterations,
+            chunk_size,
+            salt: [0; SALT_LEN],
+            nonce_prefix: [0; NONCE_PREFIX_LEN],
+        };
+        bytes.read_exact(&mut header.salt)?;
+        bytes.read_exact(&mut header.nonce_prefix)?;
+        Ok(header)
+    }
+
+    fn key(&self, passphrase: &[u8]) -> LessSafeKey {
+        let mut key = [0u8; KEY_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            self.iterations,
+            &self.salt,
+            passphrase,
+            &mut key,
+        );
+        self.cipher.key(&key)
+    }
+
+    fn nonce(&self, counter: u32, last: bool) -> Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[..NONCE_PREFIX_LEN].copy_from_slice(&self.nonce_prefix);
+        nonce[NONCE_PREFIX_LEN..11].copy_from_slice(&counter.to_be_bytes());
+        nonce[11] = last as u8;
+        Nonce::assume_unique_for_key(nonce)
+    }
+}
+
+// like read_exact, but a short read at the end of the stream is fine
+fn read_full<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Reads blocks of `size` bytes and tells which one is the last, an empty
+/// input yields a single empty last block
+fn for_each_block<R, F>(input: &mut R, size: usize, mut f: F) -> Result<(), VaultError>
+where
+    R: Read,
+    F: FnMut(u32, &[u8], bool) -> Result<(), VaultError>,
+{
+    let mut current = vec![0u8; size];
+    let mut next = vec![0u8; size];
+    let mut n = read_full(input, &mut current)?;
+    let mut counter = 0u32;
+    loop {
+        let m = if n == size {
+            read_full(input, &mut next)?
+        } else {
+            0
+        };
+        let last = m == 0;
+        f(counter, &current[..n], last)?;
+        if last {
+            return Ok(());
+        }
+        std::mem::swap(&mut current, &mut next);
+        n = m;
+        counter = counter.checked_add(1).ok_or(VaultError::TooLarge)?;
+    }
+}
+
+pub fn encrypt<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    passphrase: &[u8],
+    opts: &VaultOptions,
+) -> Result<(), VaultError> {
+    if opts.chunk_size == 0 || opts.chunk_size > MAX_CHUNK_SIZE {
+        return Err(VaultError::BadHeader("chunk size out of range"));
+    }
+    let rng = SystemRandom::new();
+    let mut header = Header {
+        cipher: opts.cipher,
+        iterations: opts.iterations,
+        chunk_size: opts.chunk_size,
+        salt: [0; SALT_LEN],
+        nonce_prefix: [0; NONCE_PREFIX_LEN],
+    };
+    // a fresh salt means a fresh key, so the nonces never repeat under one key
+    rng.fill(&mut header.salt)
+        .and_then(|_| rng.fill(&mut header.nonce_prefix))
+        .map_err(|_| io::Error::other("no system randomness"))?;
+
+    let key = header.key(passphrase);
+    let aad = header.to_bytes();
+    output.write_all(&aad)?;
+
+    let mut block = Vec::with_capacity(opts.chunk_size + TAG_LEN);
+    for_each_block(input, opts.chunk_size, |counter, chunk, last| {
+        block.clear();
+        block.extend_from_slice(chunk);
+        key.seal_in_place_append_tag(header.nonce(counter, last), Aad::from(&aad), &mut block)
+            .map_err(|_| VaultError::TooLarge)?;
+        output.write_all(&block)?;
+        Ok(())
+    })?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Plaintext is written chunk by chunk as it is authenticated, so on an
+/// error the output holds the chunks before the bad one
+pub fn decrypt<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    passphrase: &[u8],
+) -> Result<(), VaultError> {
+    let mut raw = [0u8; HEADER_LEN];
+    if read_full(input, &mut raw)? < HEADER_LEN {
+        return match raw.starts_with(MAGIC) {
+            true => Err(VaultError::Truncated),
+            false => Err(VaultError::NotAVault),
+        };
+    }
+    let header = Header::parse(&raw)?;
+    let key = header.key(passphrase);
+
+    for_each_block(
+        input,
+        header.chunk_size + TAG_LEN,
+        |counter, block, last| {
+            if block.len() < TAG_LEN {
+                return Err(VaultError::Truncated);
+            }
+            let mut block = block.to_vec();
+            let plain = key
+                .open_in_place(header.nonce(counter, last), Aad::from(&raw), &mut block)
+                .map_err(|_| VaultError::Authentication { chunk: counter })?;
+            output.write_all(plain)?;
+            Ok(())
+        },
+    )?;
+    output.flush()?;
+    Ok(())
+}
+
+pub fn encrypt_file(
+    path: &Path,
+    out: &Path,
+    passphrase: &[u8],
+    opts: &VaultOptions,
+) -> Result<(), VaultError> {
+    let mut input = BufReader::new(File::open(path)?);
+    write_atomically(out, |w| encrypt(&mut input, w, passphrase, opts))
+}
+
+/// The output only appears once the whole file has been authenticated
+pub fn decrypt_file(path: &Path, out: &Path, passphrase: &[u8]) -> Result<(), VaultError> {
+    let mut input = BufReader::new(File::open(path)?);
+    write_atomically(out, |w| decrypt(&mut input, w, passphrase))
+}
+
+/// Passphrase from `$VAULT_PASSPHRASE`, otherwise read from stdin.
+/// Note that the terminal echoes it.
+pub fn read_passphrase(confirm: bool) -> io::Result<String> {
+    if let Ok(pass) = std::env::var("VAULT_PASSPHRASE") {
+        return Ok(pass);
+    }
+    let read = |prompt: &str| -> io::Result<String> {
+        eprint!("{}", prompt);
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    };
+    let pass = read("Passphrase: ")?;
+    if confirm && read("Repeat passphrase: ")? != pass {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the passphrases differ",
+        ));
+    }
+    Ok(pass)
+}
+
+// writes into a temporary file next to `out` and renames it on success
+fn write_atomically<F>(out: &Path, f: F) -> Result<(), VaultError>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> Result<(), VaultError>,
+{
+    let dir = match out.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let tmp = tempfile::NamedTempFile::new_in(dir)?;
+    f(&mut BufWriter::new(tmp.as_file()))?;
+    tmp.persist(out).map_err(|e| VaultError::Io(e.error))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASS: &[u8] = b"correct horse battery staple";
+
+    fn opts(cipher: Cipher) -> VaultOptions {
+        VaultOptions {
+            cipher,
+            iterations: NonZeroU32::new(10).unwrap(),
+            chunk_size: 16,
+        }
+    }
+
+    fn seal(data: &[u8], cipher: Cipher) -> Vec<u8> {
+        let mut out = Vec::new();
+        encrypt(&mut &data[..], &mut out, PASS, &opts(cipher)).unwrap();
+        out
+    }
+
+    fn open(data: &[u8], passphrase: &[u8]) -> Result<Vec<u8>, VaultError> {
+        let mut out = Vec::new();
+        decrypt(&mut &data[..], &mut out, passphrase).map(|_| out)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for cipher in Cipher::ALL {
+            for len in [0usize, 1, 15, 16, 17, 48, 100] {
+                let data: Vec<u8> = (0..len as u8).collect();
+                let sealed = seal(&data, cipher);
+                let chunks = len.div_ceil(16).max(1);
+                assert_eq!(sealed.len(), HEADER_LEN + len + chunks * TAG_LEN);
+                assert_eq!(open(&sealed, PASS).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let sealed = seal(b"secret", Cipher::ChaCha20Poly1305);
+        assert!(matches!(
+            open(&sealed, b"guess"),
+            Err(VaultError::Authentication { chunk: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let data = [7u8; 40];
+        let sealed = seal(&data, Cipher::Aes256Gcm);
+        let block = 16 + TAG_LEN;
+        let chunk = |i: usize| {
+            &sealed[HEADER_LEN + i * block..(HEADER_LEN + (i + 1) * block).min(sealed.len())]
+        };
+
+        let mut flipped = sealed.clone();
+        flipped[HEADER_LEN + block + 3] ^= 1;
+        assert!(matches!(
+            open(&flipped, PASS),
+            Err(VaultError::Authentication { chunk: 1 })
+        ));
+
+        // the header is authenticated too, here the iteration count
+        let mut header = sealed.clone();
+        header[9] ^= 1;
+        assert!(matches!(
+            open(&header, PASS),
+            Err(VaultError::Authentication { chunk: 0 })
+        ));
+
+        let swapped = [&sealed[..HEADER_LEN], chunk(1), chunk(0), chunk(2)].concat();
+        assert!(matches!(
+            open(&swapped, PASS),
+            Err(VaultError::Authentication { chunk: 0 })
+        ));
+
+        // cut at a chunk boundary, the new last chunk was not sealed as the last one
+        let truncated = &sealed[..HEADER_LEN + 2 * block];
+        assert!(matches!(
+            open(truncated, PASS),
+            Err(VaultError::Authentication { chunk: 1 })
+        ));
+        assert!(matches!(
+            open(&sealed[..HEADER_LEN + 5], PASS),
+            Err(VaultError::Truncated)
+        ));
+        assert!(matches!(
+            open(&sealed[..10], PASS),
+            Err(VaultError::Truncated)
+        ));
+
+        let appended = [&sealed[..], chunk(0)].concat();
+        assert!(matches!(
+            open(&appended, PASS),
+            Err(VaultError::Authentication { chunk: 2 })
+        ));
+
+        assert!(matches!(
+            open(b"plain text file", PASS),
+            Err(VaultError::NotAVault)
+        ));
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (plain, sealed, opened) = (
+            dir.path().join("plain.txt"),
+            dir.path().join("plain.txt.vault"),
+            dir.path().join("opened.txt"),
+        );
+        std::fs::write(&plain, vec![42u8; 1000]).unwrap();
+        encrypt_file(&plain, &sealed, PASS, &opts(Cipher::Aes256Gcm)).unwrap();
+        assert!(decrypt_file(&sealed, &opened, b"wrong").is_err());
+        assert!(!opened.exists());
+        decrypt_file(&sealed, &opened, PASS).unwrap();
+        assert_eq!(std::fs::read(opened).unwrap(), vec![42u8; 1000]);
+    }
+}
@@ -1,5 +1,7 @@
 use bitflags::bitflags;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
+use std::str::FromStr;
 
 bitflags! {
     #[derive(Debug, Eq, PartialEq, Clone, Copy)]
@@ -28,7 +30,35 @@ impl fmt::Display for MyFlags {
     }
 }
 
-#[allow(dead_code)]
+impl FromStr for MyFlags {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        flag_set::parse(s)
+    }
+}
+
+impl Serialize for MyFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        flag_set::as_bits::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MyFlags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        flag_set::as_bits::deserialize(deserializer)
+    }
+}
+
+pub fn main() {
+    bit_flags();
+    println!("Flags: {}", MyFlags::FLAG_A | MyFlags::FLAG_C);
+    bitset::main();
+    bloom::main();
+    super::cb_6_lru_cache::main();
+    super::cb_6_indexed_heap::main();
+}
+
 fn bit_flags() {
     let e1 = MyFlags::FLAG_A | MyFlags::FLAG_C;
     let e2 = MyFlags::FLAG_B | MyFlags::FLAG_C;
@@ -48,6 +78,970 @@ fn bit_flags() {
         format!("{:?}", MyFlags::FLAG_A | MyFlags::FLAG_B),
         "MyFlags(FLAG_A | FLAG_B)"
     );
+
+    let parsed: MyFlags = "FLAG_A | FLAG_C".parse().unwrap();
+    assert_eq!(parsed, MyFlags::FLAG_A | MyFlags::FLAG_C);
+    assert_eq!(serde_json::to_string(&parsed).unwrap(), "5");
+}
+
+/// Text and serde support for any `bitflags!` type.
+///
+/// Flags parse from names separated by `|`, where `0x..` stands for raw bits
+/// such as `FLAG_A | 0x40`. With serde they are written either as a list of
+/// names or as an integer, and both forms (plus the `|` string) are read back.
+/// Bits without a name survive all the round trips but log a warning.
+pub mod flag_set {
+    use bitflags::parser::{self, ParseHex};
+    use bitflags::Flags;
+    use serde::de::{self, SeqAccess, Visitor};
+    use serde::ser::SerializeSeq;
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    /// The bits of `flags` that don't belong to any named flag
+    pub fn unknown_bits<F: Flags>(flags: &F) -> u64
+    where
+        F::Bits: Into<u64>,
+    {
+        flags.bits().into() & !F::all().bits().into()
+    }
+
+    fn warn_unknown<F: Flags>(flags: F) -> F
+    where
+        F::Bits: Into<u64>,
+    {
+        let unknown = unknown_bits(&flags);
+        if unknown != 0 {
+            log::warn!(
+                "unknown bits {:#x} in {}",
+                unknown,
+                std::any::type_name::<F>()
+            );
+        }
+        flags
+    }
+
+    pub fn parse<F: Flags>(s: &str) -> Result<F, String>
+    where
+        F::Bits: ParseHex + Into<u64>,
+    {
+        parser::from_str(s)
+            .map(warn_unknown)
+            .map_err(|e| format!("invalid flags '{}': {}", s.trim(), e))
+    }
+
+    /// The names of the set flags, unknown bits last as a single hex number
+    pub fn names<F: Flags>(flags: &F) -> Vec<String>
+    where
+        F::Bits: Into<u64>,
+    {
+        let mut names: Vec<String> = flags.iter_names().map(|(n, _)| n.to_string()).collect();
+        let unknown = unknown_bits(flags);
+        if unknown != 0 {
+            names.push(format!("{:#x}", unknown));
+        }
+        names
+    }
+
+    struct FlagsVisitor<F>(PhantomData<F>);
+
+    impl<'de, F: Flags> Visitor<'de> for FlagsVisitor<F>
+    where
+        F::Bits: ParseHex + Into<u64> + TryFrom<u64>,
+    {
+        type Value = F;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "a list of flag names, a '|' separated string or an integer"
+            )
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<F, E> {
+            let bits = F::Bits::try_from(v)
+                .map_err(|_| E::custom(format!("{:#x} is too wide for the flags", v)))?;
+            Ok(warn_unknown(F::from_bits_retain(bits)))
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<F, E> {
+            let v = u64::try_from(v).map_err(|_| E::custom("flags can't be negative"))?;
+            self.visit_u64(v)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<F, E> {
+            parse(v).map_err(E::custom)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<F, A::Error> {
+            let mut flags = F::empty();
+            while let Some(name) = seq.next_element::<String>()? {
+                flags.insert(parse(&name).map_err(de::Error::custom)?);
+            }
+            Ok(flags)
+        }
+    }
+
+    fn deserialize_any<'de, D, F>(deserializer: D) -> Result<F, D::Error>
+    where
+        D: Deserializer<'de>,
+        F: Flags,
+        F::Bits: ParseHex + Into<u64> + TryFrom<u64>,
+    {
+        deserializer.deserialize_any(FlagsVisitor(PhantomData))
+    }
+
+    /// `#[serde(with = "flag_set::as_names")]`
+    pub mod as_names {
+        use super::*;
+
+        pub fn serialize<F: Flags, S: Serializer>(
+            flags: &F,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error>
+        where
+            F::Bits: Into<u64>,
+        {
+            let names = names(flags);
+            let mut seq = serializer.serialize_seq(Some(names.len()))?;
+            for name in &names {
+                seq.serialize_element(name)?;
+            }
+            seq.end()
+        }
+
+        pub fn deserialize<'de, D, F>(deserializer: D) -> Result<F, D::Error>
+        where
+            D: Deserializer<'de>,
+            F: Flags,
+            F::Bits: ParseHex + Into<u64> + TryFrom<u64>,
+        {
+            deserialize_any(deserializer)
+        }
+    }
+
+    /// `#[serde(with = "flag_set::as_bits")]`
+    pub mod as_bits {
+        use super::*;
+
+        pub fn serialize<F: Flags, S: Serializer>(
+            flags: &F,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error>
+        where
+            F::Bits: Into<u64>,
+        {
+            serializer.serialize_u64(flags.bits().into())
+        }
+
+        pub fn deserialize<'de, D, F>(deserializer: D) -> Result<F, D::Error>
+        where
+            D: Deserializer<'de>,
+            F: Flags,
+            F::Bits: ParseHex + Into<u64> + TryFrom<u64>,
+        {
+            deserialize_any(deserializer)
+        }
+    }
+}
+
+/// Unix permission bits, formatted like `ls -l` does and edited like `chmod`
+pub mod permissions {
+    use super::flag_set;
+    use bitflags::bitflags;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt;
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    bitflags! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct Permissions: u32 {
+            const SETUID      = 0o4000;
+            const SETGID      = 0o2000;
+            const STICKY      = 0o1000;
+            const USER_READ   = 0o0400;
+            const USER_WRITE  = 0o0200;
+            const USER_EXEC   = 0o0100;
+            const GROUP_READ  = 0o0040;
+            const GROUP_WRITE = 0o0020;
+            const GROUP_EXEC  = 0o0010;
+            const OTHER_READ  = 0o0004;
+            const OTHER_WRITE = 0o0002;
+            const OTHER_EXEC  = 0o0001;
+        }
+    }
+
+    // user, group and other: their rwx bits and their special bit
+    const CLASSES: [(u32, Permissions, char); 3] = [
+        (0o700, Permissions::SETUID, 's'),
+        (0o070, Permissions::SETGID, 's'),
+        (0o007, Permissions::STICKY, 't'),
+    ];
+
+    impl Permissions {
+        /// The permission part of an `st_mode`, the file type bits are dropped
+        pub fn from_mode(mode: u32) -> Self {
+            Self::from_bits_truncate(mode)
+        }
+
+        /// Applies symbolic edits like `chmod` does, e.g. `u+x,g-w` or
+        /// `a=r,u+w`. Without a `ugoa` prefix the edit applies to everyone,
+        /// the umask is not consulted. `X` adds execute permission only if
+        /// some class already has it, there is no directory to check.
+        pub fn apply(self, edits: &str) -> Result<Self, String> {
+            let invalid = || format!("invalid mode '{}'", edits);
+            let mut mode = self;
+            for clause in edits.split(',') {
+                let ops = clause.find(['+', '-', '=']).ok_or_else(invalid)?;
+                let (who, mut rest) = clause.split_at(ops);
+
+                let mut classes = [false; 3];
+                for c in who.chars() {
+                    match c {
+                        'u' => classes[0] = true,
+                        'g' => classes[1] = true,
+                        'o' => classes[2] = true,
+                        'a' => classes = [true; 3],
+                        _ => return Err(invalid()),
+                    }
+                }
+                if who.is_empty() {
+                    classes = [true; 3];
+                }
+                let selected = CLASSES.iter().zip(classes).filter(|(_, on)| *on);
+                let rwx_mask: u32 = selected.clone().map(|(c, _)| c.0).sum();
+                let special_mask = selected.fold(Self::empty(), |acc, (c, _)| acc | c.1);
+
+                while let Some(op) = rest.chars().next() {
+                    // checked before slicing, `rest[1..]` needs a one byte operator
+                    if !matches!(op, '+' | '-' | '=') {
+                        return Err(invalid());
+                    }
+                    let perms_len = rest[1..]
+                        .find(|c: char| !"rwxXst".contains(c))
+                        .unwrap_or(rest.len() - 1);
+                    let perms = &rest[1..1 + perms_len];
+                    rest = &rest[1 + perms_len..];
+
+                    let mut bits = Self::empty();
+                    for p in perms.chars() {
+                        bits |= match p {
+                            'r' => Self::from_bits_truncate(0o444 & rwx_mask),
+                            'w' => Self::from_bits_truncate(0o222 & rwx_mask),
+                            'x' => Self::from_bits_truncate(0o111 & rwx_mask),
+                            'X' if mode.bits() & 0o111 != 0 => {
+                                Self::from_bits_truncate(0o111 & rwx_mask)
+                            }
+                            'X' => Self::empty(),
+                            's' => special_mask - Self::STICKY,
+                            't' => special_mask & Self::STICKY,
+                            _ => unreachable!(),
+                        };
+                    }
+                    match op {
+                        '+' => mode |= bits,
+                        '-' => mode -= bits,
+                        '=' => {
+                            mode = (mode - Self::from_bits_truncate(rwx_mask) - special_mask) | bits
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            Ok(mode)
+        }
+    }
+
+    /// `rwxr-x---`, with `s`/`S` and `t`/`T` for the special bits like `ls -l`
+    impl fmt::Display for Permissions {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            for (rwx, special, c) in CLASSES {
+                let bits = self.bits() & rwx;
+                let r = bits & 0o444 != 0;
+                let w = bits & 0o222 != 0;
+                let x = bits & 0o111 != 0;
+                let exec = match (self.contains(special), x) {
+                    (true, true) => c,
+                    (true, false) => c.to_ascii_uppercase(),
+                    (false, true) => 'x',
+                    (false, false) => '-',
+                };
+                write!(
+                    f,
+                    "{}{}{}",
+                    if r { 'r' } else { '-' },
+                    if w { 'w' } else { '-' },
+                    exec
+                )?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Either octal like `750` or `4755`, or the `rwxr-x---` form
+    impl FromStr for Permissions {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let invalid = || format!("invalid mode '{}'", s);
+            if (1..=4).contains(&s.len()) && s.chars().all(|c| c.is_digit(8)) {
+                return Ok(Self::from_mode(u32::from_str_radix(s, 8).unwrap()));
+            }
+            let chars: Vec<char> = s.chars().collect();
+            if chars.len() != 9 {
+                return Err(invalid());
+            }
+            let mut mode = Self::empty();
+            for ((rwx, special, c), triple) in CLASSES.into_iter().zip(chars.chunks(3)) {
+                let bits = match (triple[0], triple[1]) {
+                    ('r', 'w') => 0o666,
+                    ('r', '-') => 0o444,
+                    ('-', 'w') => 0o222,
+                    ('-', '-') => 0,
+                    _ => return Err(invalid()),
+                };
+                let exec = match triple[2] {
+                    '-' => 0,
+                    'x' => 0o111,
+                    e if e == c => {
+                        mode |= special;
+                        0o111
+                    }
+                    e if e == c.to_ascii_uppercase() => {
+                        mode |= special;
+                        0
+                    }
+                    _ => return Err(invalid()),
+                };
+                mode |= Self::from_bits_truncate((bits | exec) & rwx);
+            }
+            Ok(mode)
+        }
+    }
+
+    impl Serialize for Permissions {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            flag_set::as_names::serialize(self, serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Permissions {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            flag_set::as_names::deserialize(deserializer)
+        }
+    }
+
+    /// Changes the permissions of `path` to an absolute (`640`, `rw-r-----`)
+    /// or symbolic (`u+x,g-w`) mode, returns the old and the new permissions
+    pub fn chmod(path: &Path, mode: &str) -> io::Result<(Permissions, Permissions)> {
+        let old = Permissions::from_mode(fs::metadata(path)?.permissions().mode());
+        let new = mode
+            .parse()
+            .or_else(|_| old.apply(mode))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if new != old {
+            fs::set_permissions(path, fs::Permissions::from_mode(new.bits()))?;
+        }
+        Ok((old, new))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_format_and_parse() {
+            for (text, mode) in [
+                ("rwxr-x---", 0o750),
+                ("rw-r--r--", 0o644),
+                ("---------", 0),
+                ("rwsr-xr-x", 0o4755),
+                ("rwxr-Sr-T", 0o3744),
+                ("rwxrwxrwt", 0o1777),
+            ] {
+                let perms = Permissions::from_mode(mode);
+                assert_eq!(perms.to_string(), text);
+                assert_eq!(text.parse::<Permissions>().unwrap(), perms);
+            }
+            assert_eq!("640".parse(), Ok(Permissions::from_mode(0o640)));
+            assert_eq!(
+                Permissions::from_mode(0o100644),
+                Permissions::from_mode(0o644)
+            );
+            assert!("rwxr-x--".parse::<Permissions>().is_err());
+            assert!("rwxr-xq--".parse::<Permissions>().is_err());
+            assert!("8".parse::<Permissions>().is_err());
+        }
+
+        #[test]
+        fn test_symbolic_edits() {
+            let mode = |m: u32| Permissions::from_mode(m);
+            let edit = |m: u32, e: &str| mode(m).apply(e).unwrap();
+            assert_eq!(edit(0o644, "u+x,g-w"), mode(0o744));
+            assert_eq!(edit(0o664, "u+x,g-w"), mode(0o744));
+            assert_eq!(edit(0o644, "+x"), mode(0o755));
+            assert_eq!(edit(0o777, "go-w"), mode(0o755));
+            assert_eq!(edit(0o777, "o="), mode(0o770));
+            assert_eq!(edit(0o600, "a=r,u+w"), mode(0o644));
+            assert_eq!(edit(0o755, "u+s,+t"), mode(0o5755));
+            assert_eq!(edit(0o4755, "u=rw"), mode(0o655));
+            assert_eq!(edit(0o644, "a+X"), mode(0o644));
+            assert_eq!(edit(0o744, "a+X"), mode(0o755));
+            assert_eq!(edit(0o644, "u+x-r"), mode(0o344));
+            for bad in ["u", "z+x", "u+q", "u+x,", "g=u", "u+xé", "é+x"] {
+                assert!(mode(0o644).apply(bad).is_err(), "{}", bad);
+            }
+        }
+
+        #[test]
+        fn test_serde() {
+            let perms = Permissions::from_mode(0o4750);
+            let json = serde_json::to_string(&perms).unwrap();
+            assert_eq!(
+                json,
+                r#"["SETUID","USER_READ","USER_WRITE","USER_EXEC","GROUP_READ","GROUP_EXEC"]"#
+            );
+            assert_eq!(serde_json::from_str::<Permissions>(&json).unwrap(), perms);
+            assert_eq!(serde_json::from_str::<Permissions>("2536").unwrap(), perms);
+            assert_eq!(
+                serde_json::from_str::<Permissions>(r#""USER_READ | OTHER_EXEC""#).unwrap(),
+                Permissions::from_mode(0o401)
+            );
+        }
+
+        #[test]
+        fn test_chmod() {
+            let file = tempfile::NamedTempFile::new().unwrap();
+            chmod(file.path(), "600").unwrap();
+            let (old, new) = chmod(file.path(), "u+x,g+r").unwrap();
+            assert_eq!((old.bits(), new.bits()), (0o600, 0o740));
+            let (old, new) = chmod(file.path(), "rw-------").unwrap();
+            assert_eq!(
+                (old.to_string(), new.to_string()),
+                ("rwxr-----".into(), "rw-------".into())
+            );
+            assert!(chmod(file.path(), "u+y").is_err());
+        }
+    }
+}
+
+/// Growable set of small integers, one bit each
+pub mod bitset {
+    use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Sub, SubAssign};
+
+    const WORD: usize = u64::BITS as usize;
+
+    /// Bits past the last word are zero, so equal sets compare equal whatever
+    /// their capacity.
+    #[derive(Debug, Clone, Default)]
+    pub struct BitSet {
+        words: Vec<u64>,
+    }
+
+    impl BitSet {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn with_capacity(bits: usize) -> Self {
+            BitSet {
+                words: vec![0; bits.div_ceil(WORD)],
+            }
+        }
+
+        /// Wraps raw words, bit `i` is bit `i % 64` of word `i / 64`
+        pub fn from_words(words: Vec<u64>) -> Self {
+            BitSet { words }
+        }
+
+        pub fn words(&self) -> &[u64] {
+            &self.words
+        }
+
+        /// Returns true if `i` wasn't in the set yet
+        pub fn insert(&mut self, i: usize) -> bool {
+            let (w, mask) = (i / WORD, 1 << (i % WORD));
+            if w >= self.words.len() {
+                self.words.resize(w + 1, 0);
+            }
+            let new = self.words[w] & mask == 0;
+            self.words[w] |= mask;
+            new
+        }
+
+        /// Returns true if `i` was in the set
+        pub fn remove(&mut self, i: usize) -> bool {
+            let present = self.contains(i);
+            if present {
+                self.words[i / WORD] &= !(1 << (i % WORD));
+            }
+            present
+        }
+
+        pub fn contains(&self, i: usize) -> bool {
+            self.words
+                .get(i / WORD)
+                .is_some_and(|w| w & (1 << (i % WORD)) != 0)
+        }
+
+        pub fn len(&self) -> usize {
+            self.words.iter().map(|w| w.count_ones() as usize).sum()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.words.iter().all(|&w| w == 0)
+        }
+
+        pub fn clear(&mut self) {
+            self.words.clear();
+        }
+
+        /// Number of members smaller than `i`
+        pub fn rank(&self, i: usize) -> usize {
+            let (w, bit) = (i / WORD, i % WORD);
+            let full: usize = self
+                .words
+                .iter()
+                .take(w)
+                .map(|w| w.count_ones() as usize)
+                .sum();
+            let partial = match self.words.get(w) {
+                Some(word) if bit > 0 => (word & (u64::MAX >> (WORD - bit))).count_ones() as usize,
+                _ => 0,
+            };
+            full + partial
+        }
+
+        /// The member with `rank` smaller members, the inverse of `rank`
+        pub fn select(&self, rank: usize) -> Option<usize> {
+            let mut left = rank;
+            for (w, &word) in self.words.iter().enumerate() {
+                let ones = word.count_ones() as usize;
+                if left < ones {
+                    let mut word = word;
+                    for _ in 0..left {
+                        word &= word - 1; // drop the lowest set bit
+                    }
+                    return Some(w * WORD + word.trailing_zeros() as usize);
+                }
+                left -= ones;
+            }
+            None
+        }
+
+        /// Members in ascending order
+        pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+            self.words.iter().enumerate().flat_map(|(w, &word)| {
+                let mut word = word;
+                std::iter::from_fn(move || {
+                    (word != 0).then(|| {
+                        let bit = word.trailing_zeros() as usize;
+                        word &= word - 1;
+                        w * WORD + bit
+                    })
+                })
+            })
+        }
+    }
+
+    impl PartialEq for BitSet {
+        fn eq(&self, other: &Self) -> bool {
+            let (short, long) = if self.words.len() <= other.words.len() {
+                (&self.words, &other.words)
+            } else {
+                (&other.words, &self.words)
+            };
+            long[..short.len()] == short[..] && long[short.len()..].iter().all(|&w| w == 0)
+        }
+    }
+
+    impl Eq for BitSet {}
+
+    impl FromIterator<usize> for BitSet {
+        fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+            let mut set = BitSet::new();
+            set.extend(iter);
+            set
+        }
+    }
+
+    impl Extend<usize> for BitSet {
+        fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+            for i in iter {
+                self.insert(i);
+            }
+        }
+    }
+
+    impl BitOrAssign<&BitSet> for BitSet {
+        fn bitor_assign(&mut self, rhs: &BitSet) {
+            if self.words.len() < rhs.words.len() {
+                self.words.resize(rhs.words.len(), 0);
+            }
+            self.words
+                .iter_mut()
+                .zip(&rhs.words)
+                .for_each(|(a, b)| *a |= b);
+        }
+    }
+
+    impl BitAndAssign<&BitSet> for BitSet {
+        fn bitand_assign(&mut self, rhs: &BitSet) {
+            self.words.truncate(rhs.words.len());
+            self.words
+                .iter_mut()
+                .zip(&rhs.words)
+                .for_each(|(a, b)| *a &= b);
+        }
+    }
+
+    impl SubAssign<&BitSet> for BitSet {
+        fn sub_assign(&mut self, rhs: &BitSet) {
+            self.words
+                .iter_mut()
+                .zip(&rhs.words)
+                .for_each(|(a, b)| *a &= !b);
+        }
+    }
+
+    macro_rules! binary_op {
+        ($trait:ident, $method:ident, $assign:ident) => {
+            impl $trait for &BitSet {
+                type Output = BitSet;
+
+                fn $method(self, rhs: &BitSet) -> BitSet {
+                    let mut result = self.clone();
+                    result.$assign(rhs);
+                    result
+                }
+            }
+        };
+    }
+
+    binary_op!(BitOr, bitor, bitor_assign);
+    binary_op!(BitAnd, bitand, bitand_assign);
+    binary_op!(Sub, sub, sub_assign);
+
+    pub fn main() {
+        let primes: BitSet = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29].into_iter().collect();
+        let odd: BitSet = (1..30).step_by(2).collect();
+        println!(
+            "Odd primes: {:?}, even ones: {:?}",
+            (&primes & &odd).iter().collect::<Vec<_>>(),
+            (&primes - &odd).iter().collect::<Vec<_>>()
+        );
+        println!(
+            "{} primes below 20, the 5th is {:?}",
+            primes.rank(20),
+            primes.select(4)
+        );
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_insert_remove() {
+            let mut set = BitSet::new();
+            assert!(set.insert(3));
+            assert!(!set.insert(3));
+            assert!(set.insert(200));
+            assert!(set.contains(200) && !set.contains(199) && !set.contains(10_000));
+            assert_eq!(set.len(), 2);
+            assert!(set.remove(200));
+            assert!(!set.remove(200));
+            // the grown word is empty now but doesn't change equality
+            assert_eq!(set, [3].into_iter().collect());
+            set.clear();
+            assert!(set.is_empty());
+        }
+
+        #[test]
+        fn test_rank_select() {
+            let members = [0, 1, 63, 64, 65, 127, 128, 500];
+            let set: BitSet = members.into_iter().collect();
+            for (rank, &m) in members.iter().enumerate() {
+                assert_eq!(set.rank(m), rank);
+                assert_eq!(set.select(rank), Some(m));
+            }
+            assert_eq!(set.rank(62), 2);
+            assert_eq!(set.rank(10_000), members.len());
+            assert_eq!(set.select(members.len()), None);
+            assert_eq!(set.iter().collect::<Vec<_>>(), members);
+        }
+
+        #[test]
+        fn test_set_operations() {
+            let a: BitSet = [1, 2, 100].into_iter().collect();
+            let b: BitSet = [2, 3].into_iter().collect();
+            let collect = |s: BitSet| s.iter().collect::<Vec<_>>();
+            assert_eq!(collect(&a | &b), vec![1, 2, 3, 100]);
+            assert_eq!(collect(&a & &b), vec![2]);
+            assert_eq!(collect(&a - &b), vec![1, 100]);
+            assert_eq!(collect(&b - &a), vec![3]);
+            assert_eq!(&a & &b, &b & &a);
+        }
+    }
+}
+
+/// Bloom filter: a set which may answer "maybe" for items it never saw but
+/// never forgets one it did, in a fraction of the memory of a `HashSet`.
+/// Good for skipping work like already fetched URLs or already hashed files,
+/// as long as skipping an item by mistake now and then is acceptable: size it
+/// for the false-positive rate you can live with.
+pub mod bloom {
+    use super::bitset::BitSet;
+    use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+    use ring::digest;
+    use std::f64::consts::LN_2;
+    use std::io::{self, Read, Write};
+
+    const MAGIC: &[u8; 4] = b"PGBF";
+    const VERSION: u8 = 1;
+    const MAX_HASHES: u32 = 32;
+    // words read at a time, so a lying header can't make us allocate much
+    const CHUNK_WORDS: usize = 8192;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct BloomFilter {
+        bits: BitSet,
+        num_bits: u64,
+        num_hashes: u32,
+        items: u64,
+    }
+
+    impl BloomFilter {
+        /// Sized so that after `expected_items` inserts a lookup of a new
+        /// item is a false positive with probability `fp_rate`
+        pub fn new(expected_items: usize, fp_rate: f64) -> Self {
+            assert!(fp_rate > 0.0 && fp_rate < 1.0, "fp_rate must be in (0, 1)");
+            let n = expected_items.max(1) as f64;
+            let num_bits = (-n * fp_rate.ln() / (LN_2 * LN_2)).ceil().max(64.0);
+            let num_hashes = (num_bits / n * LN_2).round().clamp(1.0, MAX_HASHES as f64) as u32;
+            BloomFilter {
+                bits: BitSet::with_capacity(num_bits as usize),
+                num_bits: num_bits as u64,
+                num_hashes,
+                items: 0,
+            }
+        }
+
+        // Double hashing, the i-th probe is h1 + i * h2. SHA-256 keeps the
+        // positions the same across runs and platforms, which the
+        // serialised filters rely on.
+        fn probes(&self, item: &[u8]) -> impl Iterator<Item = usize> {
+            let hash = digest::digest(&digest::SHA256, item);
+            let h1 = u64::from_le_bytes(hash.as_ref()[..8].try_into().unwrap());
+            let h2 = u64::from_le_bytes(hash.as_ref()[8..16].try_into().unwrap()) | 1;
+            let num_bits = self.num_bits;
+            (0..self.num_hashes as u64)
+                .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+        }
+
+        /// Returns true if the item was certainly new
+        pub fn insert(&mut self, item: impl AsRef<[u8]>) -> bool {
+            let mut new = false;
+            for i in self.probes(item.as_ref()).collect::<Vec<_>>() {
+                new |= self.bits.insert(i);
+            }
+            if new {
+                self.items += 1;
+            }
+            new
+        }
+
+        /// False means never inserted, true means probably inserted
+        pub fn contains(&self, item: impl AsRef<[u8]>) -> bool {
+            self.probes(item.as_ref()).all(|i| self.bits.contains(i))
+        }
+
+        /// Number of items inserted, not counting those it already claimed to have
+        pub fn len(&self) -> u64 {
+            self.items
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.items == 0
+        }
+
+        pub fn num_bits(&self) -> u64 {
+            self.num_bits
+        }
+
+        pub fn num_hashes(&self) -> u32 {
+            self.num_hashes
+        }
+
+        /// Expected false-positive rate at the current fill
+        pub fn false_positive_rate(&self) -> f64 {
+            let fill = self.bits.len() as f64 / self.num_bits as f64;
+            fill.powi(self.num_hashes as i32)
+        }
+
+        /// `PGBF` | version u8 | hashes u32 | bits u64 | items u64 | words u64...,
+        /// all little endian
+        pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+            w.write_all(MAGIC)?;
+            w.write_u8(VERSION)?;
+            w.write_u32::<LittleEndian>(self.num_hashes)?;
+            w.write_u64::<LittleEndian>(self.num_bits)?;
+            w.write_u64::<LittleEndian>(self.items)?;
+            let words = self.num_bits.div_ceil(64) as usize;
+            for i in 0..words {
+                w.write_u64::<LittleEndian>(self.bits.words().get(i).copied().unwrap_or(0))?;
+            }
+            Ok(())
+        }
+
+        pub fn read_from<R: Read>(mut r: R) -> io::Result<Self> {
+            let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+            let mut magic = [0u8; 4];
+            r.read_exact(&mut magic)?;
+            if &magic != MAGIC {
+                return Err(invalid("not a bloom filter"));
+            }
+            if r.read_u8()? != VERSION {
+                return Err(invalid("unsupported bloom filter version"));
+            }
+            let num_hashes = r.read_u32::<LittleEndian>()?;
+            let num_bits = r.read_u64::<LittleEndian>()?;
+            let items = r.read_u64::<LittleEndian>()?;
+            if !(1..=MAX_HASHES).contains(&num_hashes) || num_bits == 0 || num_bits > 1 << 40 {
+                return Err(invalid("corrupt bloom filter header"));
+            }
+            let num_words = num_bits.div_ceil(64) as usize;
+            let mut words = Vec::new();
+            while words.len() < num_words {
+                let start = words.len();
+                words.resize(start + CHUNK_WORDS.min(num_words - start), 0);
+                r.read_u64_into::<LittleEndian>(&mut words[start..])?;
+            }
+            Ok(BloomFilter {
+                bits: BitSet::from_words(words),
+                num_bits,
+                num_hashes,
+                items,
+            })
+        }
+
+        pub fn to_bytes(&self) -> Vec<u8> {
+            let mut bytes = Vec::new();
+            self.write_to(&mut bytes).expect("writing to a Vec");
+            bytes
+        }
+
+        pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+            Self::read_from(bytes)
+        }
+    }
+
+    pub fn main() {
+        // a crawler's memory of visited pages
+        let mut seen = BloomFilter::new(1000, 0.01);
+        let links = [
+            "https://www.rust-lang.org/",
+            "https://doc.rust-lang.org/book/",
+            "https://www.rust-lang.org/",
+            "https://crates.io/",
+        ];
+        for url in links {
+            if seen.insert(url) {
+                println!("Fetching {}", url);
+            } else {
+                println!("Skipping {}, seen it before", url);
+            }
+        }
+        let bytes = seen.to_bytes();
+        println!(
+            "{} bits, {} hashes, {} bytes serialised, false positives now {:.2e}",
+            seen.num_bits(),
+            seen.num_hashes(),
+            bytes.len(),
+            seen.false_positive_rate()
+        );
+
+        // picked up again by the next run
+        let seen = BloomFilter::from_bytes(&bytes).unwrap();
+        assert!(!seen.is_empty());
+        println!(
+            "Restored {} URLs, crates.io seen: {}, docs.rs seen: {}",
+            seen.len(),
+            seen.contains("https://crates.io/"),
+            seen.contains("https://docs.rs/")
+        );
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_no_false_negatives() {
+            let mut filter = BloomFilter::new(1000, 0.01);
+            assert!(filter.is_empty());
+            for i in 0..1000 {
+                filter.insert(format!("/files/{}", i));
+            }
+            for i in 0..1000 {
+                assert!(filter.contains(format!("/files/{}", i)));
+            }
+            assert!(filter.len() > 990);
+            assert!(!filter.insert("/files/7"));
+        }
+
+        #[test]
+        fn test_false_positive_rate() {
+            for fp_rate in [0.1, 0.01, 0.001] {
+                let mut filter = BloomFilter::new(5000, fp_rate);
+                for i in 0..5000 {
+                    filter.insert(format!("in {}", i));
+                }
+                let false_positives = (0..20_000)
+                    .filter(|i| filter.contains(format!("out {}", i)))
+                    .count();
+                let measured = false_positives as f64 / 20_000.0;
+                assert!(measured < fp_rate * 1.5, "{} for {}", measured, fp_rate);
+                let estimate = filter.false_positive_rate();
+                assert!(estimate < fp_rate * 1.5, "{} for {}", estimate, fp_rate);
+            }
+        }
+
+        #[test]
+        fn test_serialisation() {
+            let mut filter = BloomFilter::new(100, 0.05);
+            filter.insert("https://example.com/");
+            filter.insert(b"\x00raw bytes");
+            let bytes = filter.to_bytes();
+            assert_eq!(
+                bytes.len(),
+                25 + 8 * filter.num_bits().div_ceil(64) as usize
+            );
+
+            let copy = BloomFilter::from_bytes(&bytes).unwrap();
+            assert_eq!(copy, filter);
+            assert!(copy.contains("https://example.com/"));
+            assert!(!copy.contains("https://example.org/"));
+
+            assert!(BloomFilter::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+            let mut bad = bytes.clone();
+            bad[0] = b'X';
+            assert!(BloomFilter::from_bytes(&bad).is_err());
+
+            // a header promising 2^40 bits with no payload fails cheaply
+            let mut huge = bytes[..25].to_vec();
+            huge[9..17].copy_from_slice(&(1u64 << 40).to_le_bytes());
+            assert!(BloomFilter::from_bytes(&huge).is_err());
+            let mut many_hashes = bytes.clone();
+            many_hashes[5..9].copy_from_slice(&33u32.to_le_bytes());
+            assert!(BloomFilter::from_bytes(&many_hashes).is_err());
+        }
+    }
 }
 
 #[cfg(test)]
@@ -58,4 +1052,43 @@ mod tests {
     fn test_bit_flags() {
         bit_flags();
     }
+
+    #[test]
+    fn test_flag_set() {
+        assert_eq!("".parse(), Ok(MyFlags::empty()));
+        assert_eq!(
+            " FLAG_B|FLAG_C ".parse(),
+            Ok(MyFlags::FLAG_B | MyFlags::FLAG_C)
+        );
+        assert!("FLAG_A | FLAG_D".parse::<MyFlags>().is_err());
+        assert!("FLAG_A |".parse::<MyFlags>().is_err());
+
+        // unknown bits are kept, but stand out
+        let odd: MyFlags = "FLAG_A | 0x40".parse().unwrap();
+        assert_eq!(odd.bits(), 0x41);
+        assert_eq!(flag_set::unknown_bits(&odd), 0x40);
+        assert_eq!(flag_set::names(&odd), vec!["FLAG_A", "0x40"]);
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Config {
+            #[serde(with = "flag_set::as_names")]
+            names: MyFlags,
+            #[serde(with = "flag_set::as_bits")]
+            bits: MyFlags,
+        }
+        let config = Config {
+            names: odd,
+            bits: odd,
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(json, r#"{"names":["FLAG_A","0x40"],"bits":65}"#);
+        assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), config);
+
+        // either form is accepted whatever the field writes
+        let swapped = r#"{"names":65,"bits":"FLAG_A | 0x40"}"#;
+        assert_eq!(serde_json::from_str::<Config>(swapped).unwrap(), config);
+        assert!(serde_json::from_str::<MyFlags>("-1").is_err());
+        assert!(serde_json::from_str::<MyFlags>("4294967296").is_err());
+        assert!(serde_json::from_str::<MyFlags>(r#"["FLAG_X"]"#).is_err());
+    }
 }
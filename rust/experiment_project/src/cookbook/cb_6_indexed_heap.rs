@@ -0,0 +1,360 @@
+// Indexed d-ary min-heap. Every pushed item gets a handle, through which its
+// priority can be changed or the item removed in O(log n), something
+// BinaryHeap can only emulate by pushing duplicates and skipping stale ones.
+// The entries sit in a slab of slots; the heap array holds slot numbers and
+// every entry remembers its position in it.
+
+const DEFAULT_ARITY: usize = 4;
+
+/// Refers to one pushed item. Once the item is popped or removed, the handle
+/// stays invalid even if its slot is reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    slot: usize,
+    generation: u64,
+}
+
+struct Entry<P, T> {
+    priority: P,
+    value: T,
+    pos: usize,
+}
+
+struct Slot<P, T> {
+    generation: u64,
+    entry: Option<Entry<P, T>>,
+}
+
+/// Pops the smallest priority first, unlike `BinaryHeap`. Wrap priorities in
+/// `std::cmp::Reverse` for the largest first.
+pub struct IndexedHeap<P, T> {
+    arity: usize,
+    heap: Vec<usize>,
+    slots: Vec<Slot<P, T>>,
+    free: Vec<usize>,
+}
+
+impl<P: Ord, T> Default for IndexedHeap<P, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Ord, T> IndexedHeap<P, T> {
+    /// A 4-ary heap, shallower than a binary one and its children share a
+    /// cache line, which usually pays off when priorities change a lot
+    pub fn new() -> Self {
+        Self::with_arity(DEFAULT_ARITY)
+    }
+
+    pub fn with_arity(arity: usize) -> Self {
+        assert!(arity >= 2, "a heap needs at least two children per node");
+        IndexedHeap {
+            arity,
+            heap: Vec::new(),
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    fn entry(&self, slot: usize) -> &Entry<P, T> {
+        self.slots[slot]
+            .entry
+            .as_ref()
+            .expect("heap slot is occupied")
+    }
+
+    fn priority_at(&self, pos: usize) -> &P {
+        &self.entry(self.heap[pos]).priority
+    }
+
+    fn place(&mut self, pos: usize, slot: usize) {
+        self.heap[pos] = slot;
+        self.slots[slot]
+            .entry
+            .as_mut()
+            .expect("heap slot is occupied")
+            .pos = pos;
+    }
+
+    // the moving entry is written once at its final position
+    fn sift_up(&mut self, mut pos: usize) {
+        let slot = self.heap[pos];
+        while pos > 0 {
+            let parent = (pos - 1) / self.arity;
+            if self.entry(slot).priority >= *self.priority_at(parent) {
+                break;
+            }
+            self.place(pos, self.heap[parent]);
+            pos = parent;
+        }
+        self.place(pos, slot);
+    }
+
+    fn sift_down(&mut self, mut pos: usize) {
+        let slot = self.heap[pos];
+        loop {
+            let first = pos * self.arity + 1;
+            if first >= self.heap.len() {
+                break;
+            }
+            let last = (first + self.arity).min(self.heap.len());
+            let child = (first + 1..last).fold(first, |best, c| {
+                if self.priority_at(c) < self.priority_at(best) {
+                    c
+                } else {
+                    best
+                }
+            });
+            if *self.priority_at(child) >= self.entry(slot).priority {
+                break;
+            }
+            self.place(pos, self.heap[child]);
+            pos = child;
+        }
+        self.place(pos, slot);
+    }
+
+    fn slot_of(&self, handle: Handle) -> Option<usize> {
+        let slot = self.slots.get(handle.slot)?;
+        (slot.generation == handle.generation && slot.entry.is_some()).then_some(handle.slot)
+    }
+
+    pub fn push(&mut self, priority: P, value: T) -> Handle {
+        let slot = self.free.pop().unwrap_or_else(|| {
+            self.slots.push(Slot {
+                generation: 0,
+                entry: None,
+            });
+            self.slots.len() - 1
+        });
+        let pos = self.heap.len();
+        self.slots[slot].entry = Some(Entry {
+            priority,
+            value,
+            pos,
+        });
+        self.heap.push(slot);
+        self.sift_up(pos);
+        Handle {
+            slot,
+            generation: self.slots[slot].generation,
+        }
+    }
+
+    /// The item with the smallest priority
+    pub fn peek(&self) -> Option<(&P, &T)> {
+        let entry = self.entry(*self.heap.first()?);
+        Some((&entry.priority, &entry.value))
+    }
+
+    pub fn pop(&mut self) -> Option<(P, T)> {
+        let slot = *self.heap.first()?;
+        Some(self.take(slot))
+    }
+
+    fn take(&mut self, slot: usize) -> (P, T) {
+        let pos = self.entry(slot).pos;
+        self.heap.swap_remove(pos);
+        if pos < self.heap.len() {
+            // the last entry took its place and may belong either way
+            let moved = self.heap[pos];
+            self.place(pos, moved);
+            self.sift_up(pos);
+            self.sift_down(self.entry(moved).pos);
+        }
+        let slot_ref = &mut self.slots[slot];
+        let entry = slot_ref.entry.take().expect("heap slot is occupied");
+        slot_ref.generation += 1;
+        self.free.push(slot);
+        (entry.priority, entry.value)
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<(&P, &T)> {
+        let entry = self.entry(self.slot_of(handle)?);
+        Some((&entry.priority, &entry.value))
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.slot_of(handle).is_some()
+    }
+
+    /// Sets a new priority and returns the old one, `None` if the item is
+    /// no longer in the heap
+    pub fn change_priority(&mut self, handle: Handle, priority: P) -> Option<P> {
+        let slot = self.slot_of(handle)?;
+        let entry = self.slots[slot]
+            .entry
+            .as_mut()
+            .expect("heap slot is occupied");
+        let decreased = priority < entry.priority;
+        let old = std::mem::replace(&mut entry.priority, priority);
+        let pos = entry.pos;
+        if decreased {
+            self.sift_up(pos);
+        } else {
+            self.sift_down(pos);
+        }
+        Some(old)
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<(P, T)> {
+        let slot = self.slot_of(handle)?;
+        Some(self.take(slot))
+    }
+}
+
+pub fn main() {
+    // a job scheduler, the smallest deadline runs first
+    let mut jobs = IndexedHeap::new();
+    let backup = jobs.push(300, "backup");
+    let report = jobs.push(120, "report");
+    let cleanup = jobs.push(600, "cleanup");
+
+    // the backup became urgent and the cleanup was cancelled
+    jobs.change_priority(backup, 60);
+    jobs.remove(cleanup);
+    if let Some((deadline, job)) = jobs.peek() {
+        println!("Next job: {} at {}s", job, deadline);
+    }
+    if let Some((deadline, _)) = jobs.get(report) {
+        println!("Report still queued for {}s", deadline);
+    }
+    while !jobs.is_empty() {
+        let (deadline, job) = jobs.pop().expect("not empty");
+        println!("Running {} (deadline {}s)", job, deadline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::cmp::Reverse;
+
+    #[test]
+    fn test_heap_order() {
+        for arity in [2, 3, 4, 8] {
+            let mut heap = IndexedHeap::with_arity(arity);
+            for (i, p) in [5, 3, 9, 1, 7, 3, 0, 8].into_iter().enumerate() {
+                heap.push(p, i);
+            }
+            assert_eq!(heap.peek(), Some((&0, &6)));
+            let mut popped = Vec::new();
+            while let Some((p, _)) = heap.pop() {
+                popped.push(p);
+            }
+            assert_eq!(popped, vec![0, 1, 3, 3, 5, 7, 8, 9]);
+            assert!(heap.is_empty());
+        }
+
+        let mut max_first = IndexedHeap::new();
+        max_first.push(Reverse(1), "low");
+        max_first.push(Reverse(10), "high");
+        assert_eq!(max_first.pop(), Some((Reverse(10), "high")));
+    }
+
+    #[test]
+    fn test_handles() {
+        let mut heap = IndexedHeap::with_arity(2);
+        let a = heap.push(10, "a");
+        let b = heap.push(20, "b");
+        let c = heap.push(30, "c");
+
+        assert_eq!(heap.change_priority(c, 5), Some(30));
+        assert_eq!(heap.peek(), Some((&5, &"c")));
+        assert_eq!(heap.change_priority(c, 25), Some(5));
+        assert_eq!(heap.peek(), Some((&10, &"a")));
+        assert_eq!(heap.remove(a), Some((10, "a")));
+        assert_eq!(heap.get(b), Some((&20, &"b")));
+        assert_eq!(heap.len(), 2);
+
+        // a's slot is reused, but its old handle stays dead
+        let d = heap.push(1, "d");
+        assert!(!heap.contains(a) && heap.contains(d));
+        assert_eq!(heap.change_priority(a, 0), None);
+        assert_eq!(heap.remove(a), None);
+        assert_eq!(heap.pop(), Some((1, "d")));
+        assert_eq!(heap.get(d), None);
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Push(u8),
+        Pop,
+        Change(usize, u8),
+        Remove(usize),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        // few distinct priorities, so there are lots of ties
+        prop_oneof![
+            3 => (0..20u8).prop_map(Op::Push),
+            2 => Just(Op::Pop),
+            2 => (any::<usize>(), 0..20u8).prop_map(|(i, p)| Op::Change(i, p)),
+            1 => any::<usize>().prop_map(Op::Remove),
+        ]
+    }
+
+    fn check_against_model(arity: usize, ops: &[Op]) -> Result<(), TestCaseError> {
+        let mut heap = IndexedHeap::with_arity(arity);
+        // every handle ever returned, and the priority of the live ones by value
+        let mut handles = Vec::new();
+        let mut model: Vec<Option<u8>> = Vec::new();
+
+        for op in ops {
+            match *op {
+                Op::Push(p) => {
+                    handles.push(heap.push(p, model.len()));
+                    model.push(Some(p));
+                }
+                Op::Pop => {
+                    let min = model.iter().flatten().min().copied();
+                    match heap.pop() {
+                        Some((p, value)) => {
+                            // ties may come out in any order
+                            prop_assert_eq!(Some(p), min);
+                            prop_assert_eq!(model[value].take(), Some(p));
+                        }
+                        None => prop_assert_eq!(min, None),
+                    }
+                }
+                Op::Change(i, p) if !handles.is_empty() => {
+                    let i = i % handles.len();
+                    let old = heap.change_priority(handles[i], p);
+                    prop_assert_eq!(old, model[i]);
+                    if old.is_some() {
+                        model[i] = Some(p);
+                    }
+                }
+                Op::Remove(i) if !handles.is_empty() => {
+                    let i = i % handles.len();
+                    let removed = heap.remove(handles[i]);
+                    prop_assert_eq!(removed, model[i].take().map(|p| (p, i)));
+                }
+                _ => {}
+            }
+            prop_assert_eq!(heap.len(), model.iter().flatten().count());
+            prop_assert_eq!(
+                heap.peek().map(|e| *e.0),
+                model.iter().flatten().min().copied()
+            );
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_matches_model(arity in 2..6usize, ops in prop::collection::vec(op(), 0..300)) {
+            check_against_model(arity, &ops)?;
+        }
+    }
+}
@@ -0,0 +1,559 @@
+// Least recently used cache. The entries live in a slab and are chained into a
+// doubly linked list by index, most recent first, with a HashMap from key to
+// slot, so lookups, inserts and evictions are all O(1).
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+const NIL: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionReason {
+    /// pushed out to make room
+    Capacity,
+    /// its time to live was over
+    Expired,
+}
+
+type Weigher<K, V> = Box<dyn Fn(&K, &V) -> usize + Send>;
+type EvictionCallback<K, V> = Box<dyn FnMut(K, V, EvictionReason) + Send>;
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    weight: usize,
+    expires: Option<Instant>,
+    prev: usize,
+    next: usize,
+}
+
+pub struct LruCache<K, V> {
+    map: HashMap<K, usize>,
+    // None marks a free slot, its index is then in `free`
+    slots: Vec<Option<Node<K, V>>>,
+    free: Vec<usize>,
+    head: usize,
+    tail: usize,
+    weight: usize,
+    max_weight: usize,
+    weigher: Weigher<K, V>,
+    on_evict: Option<EvictionCallback<K, V>>,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    /// Holds up to `capacity` entries
+    pub fn new(capacity: usize) -> Self {
+        Self::with_weigher(capacity, |_, _| 1)
+    }
+
+    /// Holds entries up to a total weight of `max_weight`, as measured by
+    /// `weigher`, e.g. the size of a response body in bytes. An entry which
+    /// is heavier than that on its own is evicted right away, instead of
+    /// flushing everything else first.
+    pub fn with_weigher(
+        max_weight: usize,
+        weigher: impl Fn(&K, &V) -> usize + Send + 'static,
+    ) -> Self {
+        LruCache {
+            map: HashMap::new(),
+            slots: Vec::new(),
+            free: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            weight: 0,
+            max_weight,
+            weigher: Box::new(weigher),
+            on_evict: None,
+        }
+    }
+
+    /// Called with every entry evicted for lack of room or because it
+    /// expired, but not for those removed or replaced explicitly
+    pub fn on_evict(mut self, callback: impl FnMut(K, V, EvictionReason) + Send + 'static) -> Self {
+        self.on_evict = Some(Box::new(callback));
+        self
+    }
+
+    fn node(&self, i: usize) -> &Node<K, V> {
+        self.slots[i].as_ref().expect("linked slot is occupied")
+    }
+
+    fn node_mut(&mut self, i: usize) -> &mut Node<K, V> {
+        self.slots[i].as_mut().expect("linked slot is occupied")
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let (prev, next) = (self.node(i).prev, self.node(i).next);
+        match prev {
+            NIL => self.head = next,
+            p => self.node_mut(p).next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            n => self.node_mut(n).prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, i: usize) {
+        let head = self.head;
+        let node = self.node_mut(i);
+        node.prev = NIL;
+        node.next = head;
+        match head {
+            NIL => self.tail = i,
+            h => self.node_mut(h).prev = i,
+        }
+        self.head = i;
+    }
+
+    fn take(&mut self, i: usize) -> Node<K, V> {
+        self.unlink(i);
+        let node = self.slots[i].take().expect("linked slot is occupied");
+        self.free.push(i);
+        self.map.remove(&node.key);
+        self.weight -= node.weight;
+        node
+    }
+
+    fn evict(&mut self, i: usize, reason: EvictionReason) {
+        let node = self.take(i);
+        if let Some(callback) = self.on_evict.as_mut() {
+            callback(node.key, node.value, reason);
+        }
+    }
+
+    // the slot of a live entry, an expired one is evicted on the way
+    fn live_slot(&mut self, key: &K) -> Option<usize> {
+        let i = *self.map.get(key)?;
+        if self.node(i).expires.is_some_and(|t| Instant::now() >= t) {
+            self.evict(i, EvictionReason::Expired);
+            return None;
+        }
+        Some(i)
+    }
+
+    /// Looks the entry up and marks it as the most recently used
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let i = self.live_slot(key)?;
+        self.unlink(i);
+        self.push_front(i);
+        Some(&self.node(i).value)
+    }
+
+    /// Looks the entry up without touching its position
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        let node = self.node(*self.map.get(key)?);
+        let live = node.expires.is_none_or(|t| Instant::now() < t);
+        live.then_some(&node.value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    /// Stores `value` as the most recently used entry and evicts from the
+    /// least recently used end until it fits. Returns the replaced value.
+    pub fn put(&mut self, key: K, value: V) -> Option<V> {
+        self.insert(key, value, None)
+    }
+
+    /// Like `put`, but the entry expires after `ttl`
+    pub fn put_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.insert(key, value, Instant::now().checked_add(ttl))
+    }
+
+    fn insert(&mut self, key: K, value: V, expires: Option<Instant>) -> Option<V> {
+        let old = self.live_slot(&key).map(|i| self.take(i).value);
+        let weight = (self.weigher)(&key, &value);
+        if weight > self.max_weight {
+            if let Some(callback) = self.on_evict.as_mut() {
+                callback(key, value, EvictionReason::Capacity);
+            }
+            return old;
+        }
+        let node = Node {
+            key: key.clone(),
+            value,
+            weight,
+            expires,
+            prev: NIL,
+            next: NIL,
+        };
+        let i = match self.free.pop() {
+            Some(i) => {
+                self.slots[i] = Some(node);
+                i
+            }
+            None => {
+                self.slots.push(Some(node));
+                self.slots.len() - 1
+            }
+        };
+        self.map.insert(key, i);
+        self.weight += weight;
+        self.push_front(i);
+
+        while self.weight > self.max_weight {
+            self.evict(self.tail, EvictionReason::Capacity);
+        }
+        old
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.live_slot(key).map(|i| self.take(i).value)
+    }
+
+    /// Evicts all the expired entries, returns how many there were
+    pub fn purge_expired(&mut self) -> usize {
+        let now = Instant::now();
+        let expired: Vec<usize> = self
+            .map
+            .values()
+            .copied()
+            .filter(|&i| self.node(i).expires.is_some_and(|t| now >= t))
+            .collect();
+        for &i in &expired {
+            self.evict(i, EvictionReason::Expired);
+        }
+        expired.len()
+    }
+
+    /// Number of entries, expired ones count until they are noticed or purged
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Sum of the entry weights, the number of entries without a weigher
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    /// Keys from the most to the least recently used
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        let mut i = self.head;
+        std::iter::from_fn(move || {
+            (i != NIL).then(|| {
+                let node = self.node(i);
+                i = node.next;
+                &node.key
+            })
+        })
+    }
+}
+
+/// `LruCache` behind a mutex. Even a lookup reorders the list, so a read
+/// lock wouldn't do. Eviction callbacks run with the lock held and must not
+/// use the cache.
+pub struct SharedLruCache<K, V> {
+    inner: Mutex<LruCache<K, V>>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> SharedLruCache<K, V> {
+    pub fn new(cache: LruCache<K, V>) -> Self {
+        SharedLruCache {
+            inner: Mutex::new(cache),
+        }
+    }
+
+    /// For several operations under one lock
+    pub fn lock(&self) -> MutexGuard<'_, LruCache<K, V>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.lock().get(key).cloned()
+    }
+
+    pub fn put(&self, key: K, value: V) -> Option<V> {
+        self.lock().put(key, value)
+    }
+
+    pub fn put_with_ttl(&self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.lock().put_with_ttl(key, value, ttl)
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.lock().remove(key)
+    }
+
+    /// The cached value of `key`, computed by `f` on a miss. `f` runs
+    /// without the lock, two threads missing at once both compute it.
+    pub fn get_or_insert_with(&self, key: K, f: impl FnOnce() -> V) -> V {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        let value = f();
+        self.put(key, value.clone());
+        value
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+}
+
+pub fn main() {
+    // response bodies by URL, at most 64 bytes and for a minute each
+    let evicted = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let log = evicted.clone();
+    let cache = LruCache::with_weigher(64, |_: &String, body: &Vec<u8>| body.len())
+        .on_evict(move |url, _, reason| log.lock().unwrap().push((url, reason)));
+    let cache = SharedLruCache::new(cache);
+
+    for (url, body) in [
+        ("/index.html", "<html>home</html>"),
+        ("/about.html", "<html>about us</html>"),
+        ("/logo.svg", "<svg>a rather large logo</svg>"),
+    ] {
+        cache.put_with_ttl(
+            url.to_string(),
+            body.as_bytes().to_vec(),
+            Duration::from_secs(60),
+        );
+    }
+    let hit = cache.get_or_insert_with("/about.html".to_string(), || b"refetched".to_vec());
+    println!("Cached /about.html: {}", String::from_utf8_lossy(&hit));
+    {
+        let cache = cache.lock();
+        println!(
+            "{} entries, {} bytes, most recent first: {:?}",
+            cache.len(),
+            cache.weight(),
+            cache.keys().collect::<Vec<_>>()
+        );
+    }
+    println!("Evicted: {:?}", evicted.lock().unwrap());
+    cache.remove(&"/logo.svg".to_string());
+    assert!(!cache.is_empty());
+    assert!(cache.lock().contains_key(&"/about.html".to_string()));
+    cache.lock().purge_expired();
+    println!("{} entries after removing /logo.svg", cache.len());
+
+    // bounded by the number of entries instead of their weight
+    let mut resolved = LruCache::new(2);
+    for (host, addr) in [
+        ("rust-lang.org", "192.0.2.10"),
+        ("crates.io", "192.0.2.20"),
+        ("docs.rs", "192.0.2.30"),
+    ] {
+        resolved.put(host, addr);
+    }
+    println!("Resolved hosts: {:?}", resolved.keys().collect::<Vec<_>>());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::VecDeque;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_lru_order() {
+        let mut cache = LruCache::new(2);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        cache.put("c", 3); // b is the least recently used
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), vec!["c", "a"]);
+        assert_eq!(cache.peek(&"b"), None);
+        // peek doesn't refresh, so a goes next
+        assert_eq!(cache.peek(&"a"), Some(&1));
+        assert_eq!(cache.put("d", 4), None);
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), vec!["d", "c"]);
+        assert_eq!(cache.put("c", 30), Some(3));
+        assert_eq!(cache.remove(&"d"), Some(4));
+        assert_eq!(cache.len(), 1);
+
+        let mut none = LruCache::new(0);
+        none.put("a", 1);
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn test_weight_and_callbacks() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+        let mut cache = LruCache::with_weigher(10, |_, v: &String| v.len())
+            .on_evict(move |k, _, reason| log.lock().unwrap().push((k, reason)));
+        cache.put(1, "aaaa".to_string());
+        cache.put(2, "bbbb".to_string());
+        cache.put(3, "cc".to_string());
+        assert_eq!(cache.weight(), 10);
+        cache.put(4, "ddd".to_string()); // 1 has to go
+        cache.put(5, "x".repeat(11)); // too heavy for the whole cache
+        cache.put(2, "b".to_string()); // replaced, not evicted
+        assert_eq!(cache.weight(), 6);
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), vec![2, 4, 3]);
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            vec![(1, EvictionReason::Capacity), (5, EvictionReason::Capacity)]
+        );
+    }
+
+    #[test]
+    fn test_ttl() {
+        let expired = Arc::new(Mutex::new(Vec::new()));
+        let log = expired.clone();
+        let mut cache =
+            LruCache::new(10).on_evict(move |k, _, reason| log.lock().unwrap().push((k, reason)));
+        cache.put_with_ttl("short", 1, Duration::from_millis(20));
+        cache.put_with_ttl("shorter", 2, Duration::from_millis(20));
+        cache.put("forever", 3);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.peek(&"short"), None);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(&"short"), None);
+        assert_eq!(cache.purge_expired(), 1);
+        assert_eq!(cache.get(&"forever"), Some(&3));
+        assert_eq!(cache.len(), 1);
+        let mut expired = expired.lock().unwrap().clone();
+        expired.sort_by_key(|e| e.0);
+        assert_eq!(
+            expired,
+            vec![
+                ("short", EvictionReason::Expired),
+                ("shorter", EvictionReason::Expired)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shared() {
+        let cache = Arc::new(SharedLruCache::new(LruCache::new(100)));
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let cache = cache.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        let key = (t * 1000 + i) % 150;
+                        let value = cache.get_or_insert_with(key, || key * 2);
+                        assert_eq!(value, key * 2);
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        assert_eq!(cache.len(), 100);
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Get(u8),
+        Peek(u8),
+        Put(u8, u8),
+        Remove(u8),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        // a small key space, so there are plenty of hits and evictions
+        prop_oneof![
+            (0..16u8).prop_map(Op::Get),
+            (0..16u8).prop_map(Op::Peek),
+            (0..16u8, 1..8u8).prop_map(|(k, w)| Op::Put(k, w)),
+            (0..16u8).prop_map(Op::Remove),
+        ]
+    }
+
+    // The obvious O(n) model: a list of (key, weight), most recent first
+    struct Model {
+        entries: VecDeque<(u8, u8)>,
+        max_weight: usize,
+        evicted: Vec<u8>,
+    }
+
+    impl Model {
+        fn position(&self, key: u8) -> Option<usize> {
+            self.entries.iter().position(|(k, _)| *k == key)
+        }
+
+        fn apply(&mut self, op: &Op) -> Option<u8> {
+            match *op {
+                Op::Get(k) => {
+                    let entry = self.entries.remove(self.position(k)?)?;
+                    self.entries.push_front(entry);
+                    Some(entry.1)
+                }
+                Op::Peek(k) => self.position(k).map(|i| self.entries[i].1),
+                Op::Put(k, w) => {
+                    let old = self.position(k).and_then(|i| self.entries.remove(i));
+                    if w as usize > self.max_weight {
+                        self.evicted.push(k);
+                        return old.map(|e| e.1);
+                    }
+                    self.entries.push_front((k, w));
+                    while self.entries.iter().map(|e| e.1 as usize).sum::<usize>() > self.max_weight
+                    {
+                        self.evicted.push(self.entries.pop_back().unwrap().0);
+                    }
+                    old.map(|e| e.1)
+                }
+                Op::Remove(k) => self
+                    .position(k)
+                    .and_then(|i| self.entries.remove(i))
+                    .map(|e| e.1),
+            }
+        }
+    }
+
+    fn check_against_model(
+        ops: &[Op],
+        max_weight: usize,
+        weighted: bool,
+    ) -> Result<(), TestCaseError> {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+        let weigher = move |_: &u8, w: &u8| if weighted { *w as usize } else { 1 };
+        let mut cache = LruCache::with_weigher(max_weight, weigher)
+            .on_evict(move |k, _, _| log.lock().unwrap().push(k));
+        let mut model = Model {
+            entries: VecDeque::new(),
+            max_weight,
+            evicted: Vec::new(),
+        };
+
+        for op in ops {
+            let op = match (op, weighted) {
+                (Op::Put(k, _), false) => Op::Put(*k, 1),
+                _ => op.clone(),
+            };
+            let got = match op {
+                Op::Get(k) => cache.get(&k).copied(),
+                Op::Peek(k) => cache.peek(&k).copied(),
+                Op::Put(k, w) => cache.put(k, w),
+                Op::Remove(k) => cache.remove(&k),
+            };
+            prop_assert_eq!(got, model.apply(&op), "{:?}", op);
+            prop_assert_eq!(
+                cache.keys().copied().collect::<Vec<_>>(),
+                model.entries.iter().map(|e| e.0).collect::<Vec<_>>()
+            );
+            prop_assert!(cache.weight() <= max_weight);
+        }
+        prop_assert_eq!(&*evicted.lock().unwrap(), &model.evicted);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_counted_eviction_matches_model(ops in prop::collection::vec(op(), 0..200), capacity in 0..10usize) {
+            check_against_model(&ops, capacity, false)?;
+        }
+
+        #[test]
+        fn prop_weighted_eviction_matches_model(ops in prop::collection::vec(op(), 0..200), max_weight in 0..30usize) {
+            check_against_model(&ops, max_weight, true)?;
+        }
+    }
+}
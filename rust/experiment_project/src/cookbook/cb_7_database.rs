@@ -20,6 +20,10 @@ pub fn main() {
         println!("Error in transations: {:}", e);
         return;
     }
+
+    if let Err(e) = register_and_login() {
+        println!("Error in register_and_login: {:}", e);
+    }
 }
 
 fn create_tables() -> Result<()> {
@@ -124,3 +128,401 @@ fn rolled_back_tx(conn: &mut Connection) -> Result<()> {
 
     tx.commit()
 }
+
+fn register_and_login() -> std::result::Result<(), users::UserError> {
+    use super::cb_5_cryptography::password::Policy;
+
+    let mut store = users::UserStore::open(":memory:", Policy::default())?;
+    store.register("alice", "correct horse battery staple")?;
+    if let Err(e) = store.register("alice", "another password") {
+        println!("Second registration refused: {}", e);
+    }
+    println!(
+        "Login with the right password: {}",
+        store.login("alice", "correct horse battery staple")?
+    );
+    println!(
+        "Login with a wrong password: {}",
+        store.login("alice", "guess")?
+    );
+    println!("Unknown user: {}", store.login("bob", "guess")?);
+
+    // stricter policy, the next successful login upgrades the stored hash
+    store.policy.iterations = store
+        .policy
+        .iterations
+        .saturating_mul(std::num::NonZeroU32::new(2).unwrap());
+    store.login("alice", "correct horse battery staple")?;
+    println!(
+        "Stored hash: {}",
+        store.password_hash("alice")?.unwrap_or_default()
+    );
+    Ok(())
+}
+
+/// Users with PHC password hashes, hashes weaker than the policy are
+/// replaced on the next successful login
+pub mod users {
+    use crate::cookbook::cb_5_cryptography::password::{
+        hash_password, verify_password, Policy, Verification,
+    };
+    use rusqlite::{params, Connection, ErrorCode, OptionalExtension};
+    use std::fmt;
+    use std::path::Path;
+
+    #[derive(Debug)]
+    pub enum UserError {
+        Db(rusqlite::Error),
+        UsernameTaken(String),
+        /// the system random number generator failed
+        Rng,
+        /// a stored hash that can't be parsed
+        BadHash(String),
+    }
+
+    impl fmt::Display for UserError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                UserError::Db(e) => write!(f, "{}", e),
+                UserError::UsernameTaken(name) => write!(f, "user '{}' already exists", name),
+                UserError::Rng => write!(f, "could not generate a salt"),
+                UserError::BadHash(e) => write!(f, "corrupt password hash: {}", e),
+            }
+        }
+    }
+
+    impl std::error::Error for UserError {}
+
+    impl From<rusqlite::Error> for UserError {
+        fn from(e: rusqlite::Error) -> Self {
+            UserError::Db(e)
+        }
+    }
+
+    pub struct UserStore {
+        conn: Connection,
+        pub policy: Policy,
+    }
+
+    impl UserStore {
+        pub fn open<P: AsRef<Path>>(path: P, policy: Policy) -> Result<UserStore, UserError> {
+            let conn = Connection::open(path)?;
+            conn.execute(
+                "create table if not exists users (
+                     id integer primary key,
+                     name text not null unique,
+                     password_hash text not null
+                 )",
+                (),
+            )?;
+            Ok(UserStore { conn, policy })
+        }
+
+        pub fn register(&mut self, name: &str, password: &str) -> Result<(), UserError> {
+            let hash = hash_password(password, &self.policy).map_err(|_| UserError::Rng)?;
+            match self.conn.execute(
+                "insert into users (name, password_hash) values (?1, ?2)",
+                params![name, hash],
+            ) {
+                Err(rusqlite::Error::SqliteFailure(e, _))
+                    if e.code == ErrorCode::ConstraintViolation =>
+                {
+                    Err(UserError::UsernameTaken(name.to_string()))
+                }
+                res => res.map(|_| ()).map_err(UserError::from),
+            }
+        }
+
+        /// `false` for an unknown user or a wrong password
+        pub fn login(&mut self, name: &str, password: &str) -> Result<bool, UserError> {
+            let Some(stored) = self.password_hash(name)? else {
+                return Ok(false);
+            };
+            match verify_password(password, &stored, &self.policy).map_err(UserError::BadHash)? {
+                Verification::Invalid => Ok(false),
+                Verification::Valid { needs_rehash } => {
+                    if needs_rehash {
+                        let hash =
+                            hash_password(password, &self.policy).map_err(|_| UserError::Rng)?;
+                        self.conn.execute(
+                            "update users set password_hash = ?1 where name = ?2",
+                            params![hash, name],
+                        )?;
+                    }
+                    Ok(true)
+                }
+            }
+        }
+
+        pub fn password_hash(&self, name: &str) -> Result<Option<String>, UserError> {
+            Ok(self
+                .conn
+                .query_row(
+                    "select password_hash from users where name = ?1",
+                    [name],
+                    |row| row.get(0),
+                )
+                .optional()?)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::num::NonZeroU32;
+
+        #[test]
+        fn test_register_login_and_rehash() {
+            let weak = Policy {
+                iterations: NonZeroU32::new(1000).unwrap(),
+                ..Default::default()
+            };
+            let mut store = UserStore::open(":memory:", weak).unwrap();
+            store.register("alice", "s3cret").unwrap();
+            assert!(matches!(
+                store.register("alice", "other"),
+                Err(UserError::UsernameTaken(_))
+            ));
+
+            assert!(store.login("alice", "s3cret").unwrap());
+            assert!(!store.login("alice", "S3cret").unwrap());
+            assert!(!store.login("bob", "s3cret").unwrap());
+            let before = store.password_hash("alice").unwrap().unwrap();
+
+            // a failed login never rehashes, a successful one under a stricter policy does
+            store.policy.iterations = NonZeroU32::new(2000).unwrap();
+            assert!(!store.login("alice", "wrong").unwrap());
+            assert_eq!(store.password_hash("alice").unwrap().unwrap(), before);
+            assert!(store.login("alice", "s3cret").unwrap());
+            let after = store.password_hash("alice").unwrap().unwrap();
+            assert!(after.starts_with("$pbkdf2-sha512$i=2000$"));
+            assert!(store.login("alice", "s3cret").unwrap());
+        }
+    }
+}
+
+/// Remembers file digests keyed by `(path, size, mtime, inode)`, so that
+/// repeated runs over the same tree only rehash files that changed.
+pub mod hash_cache {
+    use rusqlite::{params, Connection, OptionalExtension, Result};
+    use std::env;
+    use std::ffi::OsString;
+    use std::fs::Metadata;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{self, Path, PathBuf};
+
+    pub struct HashCache {
+        conn: Connection,
+        /// the database and its journal files, so a walk can leave them out
+        own_files: Vec<PathBuf>,
+    }
+
+    /// The part of the file metadata that invalidates a cached digest
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct FileStamp {
+        size: u64,
+        mtime_ns: i64,
+        inode: u64,
+    }
+
+    impl From<&Metadata> for FileStamp {
+        fn from(meta: &Metadata) -> Self {
+            FileStamp {
+                size: meta.size(),
+                mtime_ns: meta.mtime() * 1_000_000_000 + meta.mtime_nsec(),
+                inode: meta.ino(),
+            }
+        }
+    }
+
+    impl HashCache {
+        /// `$XDG_CACHE_HOME/playground/hash_cache.db`, falling back to `~/.cache`
+        pub fn default_path() -> Option<PathBuf> {
+            let base = env::var_os("XDG_CACHE_HOME")
+                .filter(|d| Path::new(d).is_absolute())
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|h| Path::new(&h).join(".cache")))?;
+            Some(base.join("playground").join("hash_cache.db"))
+        }
+
+        pub fn open<P: AsRef<Path>>(path: P) -> Result<HashCache> {
+            let path = path.as_ref();
+            let conn = Connection::open(path)?;
+            // older databases keyed the digests by the path as walked
+            conn.execute("drop table if exists file_digests", ())?;
+            conn.execute(
+                "create table if not exists digests (
+                     path blob not null,
+                     algo text not null,
+                     size integer not null,
+                     mtime_ns integer not null,
+                     inode integer not null,
+                     digest blob not null,
+                     primary key (path, algo)
+                 )",
+                (),
+            )?;
+            let db = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+            let own_files = ["", "-journal", "-wal", "-shm"]
+                .iter()
+                .map(|suffix| {
+                    let mut name = OsString::from(db.as_os_str());
+                    name.push(suffix);
+                    PathBuf::from(name)
+                })
+                .collect();
+            Ok(HashCache { conn, own_files })
+        }
+
+        /// Whether `path` is the cache database or one of its journals
+        pub fn is_own_file(&self, path: &Path) -> bool {
+            let name = path.file_name();
+            self.own_files.iter().any(|f| f.file_name() == name)
+                && path
+                    .canonicalize()
+                    .is_ok_and(|p| self.own_files.contains(&p))
+        }
+
+        // the canonical path as raw bytes, so `dir/f`, `./dir/f` and
+        // `/abs/dir/f` share an entry and non-UTF-8 names stay apart
+        fn key(path: &Path) -> Vec<u8> {
+            let path = path
+                .canonicalize()
+                .or_else(|_| path::absolute(path))
+                .unwrap_or_else(|_| path.to_owned());
+            path.as_os_str().as_bytes().to_vec()
+        }
+
+        /// Drops every cached digest
+        pub fn clear(&self) -> Result<()> {
+            self.conn.execute("delete from digests", ())?;
+            Ok(())
+        }
+
+        /// Returns the cached digest if the file has not changed since it was stored
+        pub fn get(&self, path: &Path, algo: &str, stamp: &FileStamp) -> Result<Option<Vec<u8>>> {
+            self.conn
+                .query_row(
+                    "select digest from digests
+                     where path = ?1 and algo = ?2
+                       and size = ?3 and mtime_ns = ?4 and inode = ?5",
+                    params![
+                        Self::key(path),
+                        algo,
+                        stamp.size as i64,
+                        stamp.mtime_ns,
+                        stamp.inode as i64
+                    ],
+                    |row| row.get(0),
+                )
+                .optional()
+        }
+
+        /// Stores a batch of `(path, stamp, [(algo, digest)])` in one transaction
+        pub fn put_all<'a, I>(&mut self, entries: I) -> Result<()>
+        where
+            I: IntoIterator<Item = (&'a Path, FileStamp, Vec<(&'a str, &'a [u8])>)>,
+        {
+            let tx = self.conn.transaction()?;
+            {
+                let mut stmt = tx.prepare(
+                    "insert or replace into digests (path, algo, size, mtime_ns, inode, digest)
+                     values (?1, ?2, ?3, ?4, ?5, ?6)",
+                )?;
+                for (path, stamp, digests) in entries {
+                    let key = Self::key(path);
+                    for (algo, digest) in digests {
+                        stmt.execute(params![
+                            key,
+                            algo,
+                            stamp.size as i64,
+                            stamp.mtime_ns,
+                            stamp.inode as i64,
+                            digest
+                        ])?;
+                    }
+                }
+            }
+            tx.commit()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_hash_cache_invalidation() {
+            let dir = tempfile::tempdir().unwrap();
+            let file = dir.path().join("data.bin");
+            std::fs::write(&file, b"first").unwrap();
+            let stamp = FileStamp::from(&std::fs::metadata(&file).unwrap());
+
+            let mut cache = HashCache::open(dir.path().join("cache.db")).unwrap();
+            assert_eq!(cache.get(&file, "sha256", &stamp).unwrap(), None);
+
+            let digest = [1u8, 2, 3];
+            cache
+                .put_all([(file.as_path(), stamp, vec![("sha256", &digest[..])])])
+                .unwrap();
+            assert_eq!(
+                cache.get(&file, "sha256", &stamp).unwrap(),
+                Some(digest.to_vec())
+            );
+            assert_eq!(cache.get(&file, "sha512", &stamp).unwrap(), None);
+
+            std::fs::write(&file, b"second, longer").unwrap();
+            let changed = FileStamp::from(&std::fs::metadata(&file).unwrap());
+            assert_eq!(cache.get(&file, "sha256", &changed).unwrap(), None);
+
+            cache.clear().unwrap();
+            assert_eq!(cache.get(&file, "sha256", &stamp).unwrap(), None);
+
+            assert!(cache.is_own_file(&dir.path().join("cache.db")));
+            assert!(cache.is_own_file(&dir.path().join(".").join("cache.db")));
+            assert!(!cache.is_own_file(&file));
+        }
+
+        #[test]
+        fn test_hash_cache_keys() {
+            use std::ffi::OsStr;
+
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::create_dir(dir.path().join("sub")).unwrap();
+            let file = dir.path().join("sub/data.bin");
+            std::fs::write(&file, b"data").unwrap();
+            let stamp = FileStamp::from(&std::fs::metadata(&file).unwrap());
+
+            let mut cache = HashCache::open(dir.path().join("cache.db")).unwrap();
+            let digest = [7u8; 4];
+            cache
+                .put_all([(file.as_path(), stamp, vec![("sha256", &digest[..])])])
+                .unwrap();
+            let detour = dir.path().join("sub/../sub/./data.bin");
+            assert_eq!(
+                cache.get(&detour, "sha256", &stamp).unwrap(),
+                Some(digest.to_vec())
+            );
+
+            // both would be "bad\u{FFFD}" as lossy strings
+            let a = dir.path().join(OsStr::from_bytes(b"bad\xfe"));
+            let b = dir.path().join(OsStr::from_bytes(b"bad\xff"));
+            std::fs::write(&a, b"data").unwrap();
+            std::fs::write(&b, b"data").unwrap();
+            let stamp_a = FileStamp::from(&std::fs::metadata(&a).unwrap());
+            let stamp_b = FileStamp::from(&std::fs::metadata(&b).unwrap());
+            let other = [8u8; 4];
+            cache
+                .put_all([
+                    (a.as_path(), stamp_a, vec![("sha256", &digest[..])]),
+                    (b.as_path(), stamp_b, vec![("sha256", &other[..])]),
+                ])
+                .unwrap();
+            assert_eq!(
+                cache.get(&a, "sha256", &stamp_a).unwrap(),
+                Some(digest.to_vec())
+            );
+        }
+    }
+}
@@ -9,11 +9,22 @@ pub mod cb_17_science;
 pub mod cb_18_text;
 pub mod cb_19_web_programming;
 pub mod cb_1_algorithms;
+pub mod cb_1_passgen;
 pub mod cb_2_command_line;
 pub mod cb_3_tarball;
+pub mod cb_4_bench;
+pub mod cb_4_cancel;
 pub mod cb_4_concurrency;
+pub mod cb_4_gallery;
+pub mod cb_4_kv_store;
+pub mod cb_4_par_algorithms;
+pub mod cb_4_pipeline;
 pub mod cb_5_cryptography;
+pub mod cb_5_otp;
+pub mod cb_5_vault;
 pub mod cb_6_data_structures;
+pub mod cb_6_indexed_heap;
+pub mod cb_6_lru_cache;
 pub mod cb_7_database;
 pub mod cb_8_date_time;
 pub mod cb_9_cpp;
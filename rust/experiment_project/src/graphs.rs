@@ -0,0 +1,819 @@
+// General graphs, where trees.rs only has a binary search tree. Nodes carry a
+// label and are numbered in insertion order, the algorithms work on those
+// numbers and `Graph::label` turns them back into names.
+
+use crate::cookbook::cb_6_indexed_heap::IndexedHeap;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::fmt::{self, Display, Write as _};
+use std::fs::File;
+use std::hash::Hash;
+use std::io::Read;
+use std::path::Path;
+
+pub type NodeId = usize;
+
+#[derive(Debug)]
+pub enum GraphError {
+    Csv(csv::Error),
+    /// line of the edge list and the offending weight
+    InvalidWeight(u64, f64),
+}
+
+impl Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::Csv(e) => write!(f, "{}", e),
+            GraphError::InvalidWeight(line, w) => {
+                write!(
+                    f,
+                    "line {}: weight {} is not a finite, non-negative number",
+                    line, w
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+impl From<csv::Error> for GraphError {
+    fn from(e: csv::Error) -> Self {
+        GraphError::Csv(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub weight: f64,
+}
+
+/// Adjacency list graph. In an undirected graph every edge is listed once in
+/// `edges` but shows up in the adjacency of both of its ends.
+#[derive(Debug, Clone)]
+pub struct Graph<N> {
+    directed: bool,
+    labels: Vec<N>,
+    ids: HashMap<N, NodeId>,
+    edges: Vec<Edge>,
+    // (neighbour, weight)
+    adjacency: Vec<Vec<(NodeId, f64)>>,
+}
+
+// min-heap entry for A*
+#[derive(Debug, PartialEq)]
+struct State {
+    priority: f64,
+    node: NodeId,
+}
+
+impl Eq for State {}
+
+impl Ord for State {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .priority
+            .total_cmp(&self.priority)
+            .then(other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for State {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// a path length as a heap priority
+#[derive(Debug, PartialEq)]
+struct Distance(f64);
+
+impl Eq for Distance {}
+
+impl Ord for Distance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl PartialOrd for Distance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<N: Clone + Eq + Hash> Graph<N> {
+    pub fn directed() -> Self {
+        Self::new(true)
+    }
+
+    pub fn undirected() -> Self {
+        Self::new(false)
+    }
+
+    fn new(directed: bool) -> Self {
+        Graph {
+            directed,
+            labels: Vec::new(),
+            ids: HashMap::new(),
+            edges: Vec::new(),
+            adjacency: Vec::new(),
+        }
+    }
+
+    pub fn is_directed(&self) -> bool {
+        self.directed
+    }
+
+    /// The id of `label`, which is added if it isn't in the graph yet
+    pub fn add_node(&mut self, label: N) -> NodeId {
+        if let Some(&id) = self.ids.get(&label) {
+            return id;
+        }
+        let id = self.labels.len();
+        self.labels.push(label.clone());
+        self.ids.insert(label, id);
+        self.adjacency.push(Vec::new());
+        id
+    }
+
+    /// Adds both ends as needed. Shortest paths assume weights are not negative.
+    pub fn add_edge(&mut self, from: N, to: N, weight: f64) {
+        let (from, to) = (self.add_node(from), self.add_node(to));
+        self.edges.push(Edge { from, to, weight });
+        self.adjacency[from].push((to, weight));
+        if !self.directed && from != to {
+            self.adjacency[to].push((from, weight));
+        }
+    }
+
+    pub fn id(&self, label: &N) -> Option<NodeId> {
+        self.ids.get(label).copied()
+    }
+
+    pub fn label(&self, id: NodeId) -> &N {
+        &self.labels[id]
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    pub fn neighbors(&self, id: NodeId) -> impl Iterator<Item = (NodeId, f64)> + '_ {
+        self.adjacency[id].iter().copied()
+    }
+
+    /// Nodes reachable from `start`, nearest first
+    pub fn bfs(&self, start: NodeId) -> Vec<NodeId> {
+        let mut seen = vec![false; self.node_count()];
+        let mut order = Vec::new();
+        let mut queue = VecDeque::from([start]);
+        seen[start] = true;
+        while let Some(n) = queue.pop_front() {
+            order.push(n);
+            for (m, _) in self.neighbors(n) {
+                if !seen[m] {
+                    seen[m] = true;
+                    queue.push_back(m);
+                }
+            }
+        }
+        order
+    }
+
+    /// Nodes reachable from `start` in depth first preorder, neighbours in
+    /// the order their edges were added
+    pub fn dfs(&self, start: NodeId) -> Vec<NodeId> {
+        let mut seen = vec![false; self.node_count()];
+        let mut order = Vec::new();
+        let mut stack = vec![start];
+        while let Some(n) = stack.pop() {
+            if seen[n] {
+                continue;
+            }
+            seen[n] = true;
+            order.push(n);
+            stack.extend(
+                self.adjacency[n]
+                    .iter()
+                    .rev()
+                    .map(|&(m, _)| m)
+                    .filter(|&m| !seen[m]),
+            );
+        }
+        order
+    }
+
+    /// Distance from `start` to every node, `None` for the unreachable ones.
+    /// Every node is queued at most once, a shorter way to it lowers its
+    /// priority in place.
+    pub fn dijkstra(&self, start: NodeId) -> Vec<Option<f64>> {
+        let mut dist = vec![None; self.node_count()];
+        let mut queued = vec![None; self.node_count()];
+        let mut heap = IndexedHeap::new();
+        dist[start] = Some(0.0);
+        queued[start] = Some(heap.push(Distance(0.0), start));
+        while let Some((Distance(d), node)) = heap.pop() {
+            for (m, w) in self.neighbors(node) {
+                let d = d + w;
+                if dist[m].is_some_and(|old| d >= old) {
+                    continue;
+                }
+                dist[m] = Some(d);
+                match queued[m] {
+                    Some(h) if heap.contains(h) => {
+                        heap.change_priority(h, Distance(d));
+                    }
+                    _ => queued[m] = Some(heap.push(Distance(d), m)),
+                }
+            }
+        }
+        dist
+    }
+
+    /// Shortest path from `from` to `to` with its length. `heuristic`
+    /// estimates the distance of a node to `to` and must never overestimate
+    /// it, a heuristic of 0 turns this into Dijkstra's algorithm.
+    pub fn astar(
+        &self,
+        from: NodeId,
+        to: NodeId,
+        heuristic: impl Fn(NodeId) -> f64,
+    ) -> Option<(f64, Vec<NodeId>)> {
+        let mut best = vec![f64::INFINITY; self.node_count()];
+        let mut came_from = vec![None; self.node_count()];
+        let mut heap = BinaryHeap::from([State {
+            priority: heuristic(from),
+            node: from,
+        }]);
+        best[from] = 0.0;
+        while let Some(State { priority, node }) = heap.pop() {
+            if node == to {
+                let mut path = vec![to];
+                while let Some(prev) = came_from[*path.last().unwrap()] {
+                    path.push(prev);
+                }
+                path.reverse();
+                return Some((best[to], path));
+            }
+            if priority > best[node] + heuristic(node) {
+                continue;
+            }
+            for (m, w) in self.neighbors(node) {
+                let d = best[node] + w;
+                if d < best[m] {
+                    best[m] = d;
+                    came_from[m] = Some(node);
+                    heap.push(State {
+                        priority: d + heuristic(m),
+                        node: m,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    pub fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<(f64, Vec<NodeId>)> {
+        self.astar(from, to, |_| 0.0)
+    }
+
+    /// Orders the nodes so that every edge goes forward. For a build graph
+    /// with edges from a dependency to what needs it, that's a build order.
+    /// Fails with one of the cycles, a path which starts and ends with the
+    /// same node. In an undirected graph every edge is such a cycle.
+    pub fn topological_sort(&self) -> Result<Vec<NodeId>, Vec<NodeId>> {
+        let mut in_degree = vec![0; self.node_count()];
+        for adj in &self.adjacency {
+            for &(m, _) in adj {
+                in_degree[m] += 1;
+            }
+        }
+        let mut ready: VecDeque<_> = (0..self.node_count())
+            .filter(|&n| in_degree[n] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.node_count());
+        while let Some(n) = ready.pop_front() {
+            order.push(n);
+            for (m, _) in self.neighbors(n) {
+                in_degree[m] -= 1;
+                if in_degree[m] == 0 {
+                    ready.push_back(m);
+                }
+            }
+        }
+        if order.len() == self.node_count() {
+            return Ok(order);
+        }
+
+        // Every node left over has an incoming edge from another left over
+        // node. Walking those edges backwards has to run into a cycle.
+        let stuck = |n: NodeId| in_degree[n] > 0;
+        let mut predecessor = vec![None; self.node_count()];
+        for (n, adj) in self.adjacency.iter().enumerate().filter(|(n, _)| stuck(*n)) {
+            for &(m, _) in adj {
+                if stuck(m) {
+                    predecessor[m].get_or_insert(n);
+                }
+            }
+        }
+        let mut visited_at = HashMap::new();
+        let mut walk = Vec::new();
+        let mut n = (0..self.node_count()).find(|&n| stuck(n)).unwrap();
+        while !visited_at.contains_key(&n) {
+            visited_at.insert(n, walk.len());
+            walk.push(n);
+            n = predecessor[n].unwrap();
+        }
+        let mut cycle = walk.split_off(visited_at[&n]);
+        cycle.push(n);
+        cycle.reverse();
+        Err(cycle)
+    }
+
+    /// Kosaraju's algorithm. The components come out in topological order of
+    /// the graph they form, each sorted by node id.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<NodeId>> {
+        let n = self.node_count();
+
+        // nodes by DFS finishing time, iterative to survive deep graphs
+        let mut finished = Vec::with_capacity(n);
+        let mut seen = vec![false; n];
+        for root in 0..n {
+            if seen[root] {
+                continue;
+            }
+            seen[root] = true;
+            let mut stack = vec![(root, 0)];
+            while let Some((node, next)) = stack.pop() {
+                match self.adjacency[node].get(next) {
+                    Some(&(m, _)) => {
+                        stack.push((node, next + 1));
+                        if !seen[m] {
+                            seen[m] = true;
+                            stack.push((m, 0));
+                        }
+                    }
+                    None => finished.push(node),
+                }
+            }
+        }
+
+        let mut reverse = vec![Vec::new(); n];
+        for (node, adj) in self.adjacency.iter().enumerate() {
+            for &(m, _) in adj {
+                reverse[m].push(node);
+            }
+        }
+        let mut component = vec![None; n];
+        let mut components = Vec::new();
+        for &root in finished.iter().rev() {
+            if component[root].is_some() {
+                continue;
+            }
+            let id = components.len();
+            let mut members = Vec::new();
+            let mut stack = vec![root];
+            component[root] = Some(id);
+            while let Some(node) = stack.pop() {
+                members.push(node);
+                for &m in &reverse[node] {
+                    if component[m].is_none() {
+                        component[m] = Some(id);
+                        stack.push(m);
+                    }
+                }
+            }
+            members.sort_unstable();
+            components.push(members);
+        }
+        components
+    }
+
+    /// Kruskal's algorithm, edge directions are ignored. A disconnected graph
+    /// gets a spanning forest.
+    pub fn minimum_spanning_tree(&self) -> Vec<Edge> {
+        let mut parent: Vec<NodeId> = (0..self.node_count()).collect();
+        fn root(parent: &mut [NodeId], mut n: NodeId) -> NodeId {
+            while parent[n] != n {
+                parent[n] = parent[parent[n]];
+                n = parent[n];
+            }
+            n
+        }
+
+        let mut edges = self.edges.clone();
+        edges.sort_by(|a, b| a.weight.total_cmp(&b.weight));
+        let mut tree = Vec::new();
+        for edge in edges {
+            let (a, b) = (root(&mut parent, edge.from), root(&mut parent, edge.to));
+            if a != b {
+                parent[a] = b;
+                tree.push(edge);
+            }
+        }
+        tree
+    }
+}
+
+impl<N: Clone + Eq + Hash + Display> Graph<N> {
+    /// `a -> b -> c` with the node labels
+    pub fn format_path(&self, path: &[NodeId]) -> String {
+        let sep = if self.directed { " -> " } else { " -- " };
+        path.iter()
+            .map(|&n| self.label(n).to_string())
+            .collect::<Vec<_>>()
+            .join(sep)
+    }
+
+    /// `a, b, c` with the node labels
+    pub fn format_nodes(&self, nodes: &[NodeId]) -> String {
+        nodes
+            .iter()
+            .map(|&n| self.label(n).to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Graphviz source, weights other than 1 become edge labels
+    pub fn to_dot(&self) -> String {
+        let quote = |n: NodeId| {
+            let label = self.label(n).to_string();
+            format!("\"{}\"", label.replace('\\', "\\\\").replace('"', "\\\""))
+        };
+        let (kind, arrow) = if self.directed {
+            ("digraph", "->")
+        } else {
+            ("graph", "--")
+        };
+        let mut dot = format!("{} {{\n", kind);
+        for n in 0..self.node_count() {
+            writeln!(dot, "    {};", quote(n)).unwrap();
+        }
+        for e in &self.edges {
+            write!(dot, "    {} {} {}", quote(e.from), arrow, quote(e.to)).unwrap();
+            if e.weight != 1.0 {
+                write!(dot, " [label=\"{}\"]", e.weight).unwrap();
+            }
+            dot.push_str(";\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EdgeRecord {
+    from: String,
+    to: String,
+    weight: Option<f64>,
+}
+
+impl Graph<String> {
+    /// Edge list with a `from,to[,weight]` header, the weight defaults to 1
+    pub fn from_csv<R: Read>(reader: R, directed: bool) -> Result<Self, GraphError> {
+        let mut graph = Self::new(directed);
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let headers = rdr.headers()?.clone();
+        for record in rdr.records() {
+            let record = record?;
+            let edge: EdgeRecord = record.deserialize(Some(&headers))?;
+            let weight = edge.weight.unwrap_or(1.0);
+            if !weight.is_finite() || weight < 0.0 {
+                let line = record.position().map_or(0, |p| p.line());
+                return Err(GraphError::InvalidWeight(line, weight));
+            }
+            graph.add_edge(edge.from, edge.to, weight);
+        }
+        Ok(graph)
+    }
+
+    pub fn from_csv_path(path: &Path, directed: bool) -> Result<Self, GraphError> {
+        let file = File::open(path).map_err(csv::Error::from)?;
+        Self::from_csv(file, directed)
+    }
+}
+
+/// Prints the size of `graph` and then a topological order or a cycle and the
+/// strongly connected components with more than one node, or for an
+/// undirected graph the minimum spanning tree
+pub fn analyse(graph: &Graph<String>) {
+    println!(
+        "{} nodes, {} edges, {}",
+        graph.node_count(),
+        graph.edges().len(),
+        if graph.is_directed() {
+            "directed"
+        } else {
+            "undirected"
+        }
+    );
+    if graph.is_directed() {
+        match graph.topological_sort() {
+            Ok(order) => println!("Order: {}", graph.format_path(&order)),
+            Err(cycle) => println!("Cycle: {}", graph.format_path(&cycle)),
+        }
+        for scc in graph.strongly_connected_components() {
+            if scc.len() > 1 {
+                println!("Strongly connected: {}", graph.format_nodes(&scc));
+            }
+        }
+    } else {
+        let tree = graph.minimum_spanning_tree();
+        let total: f64 = tree.iter().map(|e| e.weight).sum();
+        println!("Minimum spanning tree, total weight {}:", total);
+        for e in tree {
+            println!(
+                "  {} -- {} ({})",
+                graph.label(e.from),
+                graph.label(e.to),
+                e.weight
+            );
+        }
+    }
+}
+
+pub fn main() {
+    // crate dependencies, from the dependency to the crate which needs it
+    let mut build = Graph::directed();
+    for (dep, krate) in [
+        ("libc", "getrandom"),
+        ("getrandom", "rand_core"),
+        ("rand_core", "rand"),
+        ("libc", "rand"),
+        ("rand", "playground"),
+        ("serde", "csv"),
+        ("csv", "playground"),
+    ] {
+        build.add_edge(dep, krate, 1.0);
+    }
+    let order = build.topological_sort().unwrap();
+    println!("Build order: {}", build.format_path(&order));
+    let libc = build.id(&"libc").unwrap();
+    println!(
+        "Rebuilt when libc changes: {}",
+        build.format_nodes(&build.bfs(libc)[1..])
+    );
+    println!(
+        "Depth first from libc: {}",
+        build.format_nodes(&build.dfs(libc))
+    );
+
+    // a 4x4 grid with a wall, A* with the manhattan distance as heuristic
+    let mut grid = Graph::undirected();
+    let wall = [(1, 1), (1, 2), (2, 1)];
+    for x in 0..4i32 {
+        for y in 0..4i32 {
+            for (nx, ny) in [(x + 1, y), (x, y + 1)] {
+                let open = |p| !wall.contains(&p);
+                if nx < 4 && ny < 4 && open((x, y)) && open((nx, ny)) {
+                    grid.add_edge((x, y), (nx, ny), 1.0);
+                }
+            }
+        }
+    }
+    let (from, to) = (grid.id(&(0, 0)).unwrap(), grid.id(&(3, 3)).unwrap());
+    let manhattan = |n: NodeId| {
+        let (x, y) = *grid.label(n);
+        ((3 - x) + (3 - y)) as f64
+    };
+    let (len, path) = grid.astar(from, to, manhattan).unwrap();
+    let path: Vec<_> = path.iter().map(|&n| grid.label(n)).collect();
+    println!("A* path of length {} around the wall: {:?}", len, path);
+    let corner = grid.id(&(3, 0)).unwrap();
+    println!("(3, 0) is {:?} steps away", grid.dijkstra(from)[corner]);
+
+    let mut roads = Graph::undirected();
+    for (a, b, km) in [
+        ("A", "B", 7.0),
+        ("A", "C", 3.0),
+        ("B", "C", 1.0),
+        ("C", "D", 4.0),
+        ("B", "D", 2.0),
+    ] {
+        roads.add_edge(a.to_string(), b.to_string(), km);
+    }
+    let (a, d) = (
+        roads.id(&"A".to_string()).unwrap(),
+        roads.id(&"D".to_string()).unwrap(),
+    );
+    if let Some((km, path)) = roads.shortest_path(a, d) {
+        println!("A to D: {} km via {}", km, roads.format_path(&path));
+    }
+    analyse(&roads);
+    print!("{}", roads.to_dot());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels<'a>(graph: &'a Graph<&str>, ids: &[NodeId]) -> Vec<&'a str> {
+        ids.iter().map(|&n| *graph.label(n)).collect()
+    }
+
+    fn directed(edges: &[(&'static str, &'static str)]) -> Graph<&'static str> {
+        let mut graph = Graph::directed();
+        for &(a, b) in edges {
+            graph.add_edge(a, b, 1.0);
+        }
+        graph
+    }
+
+    #[test]
+    fn test_traversals() {
+        let graph = directed(&[
+            ("a", "b"),
+            ("a", "c"),
+            ("b", "d"),
+            ("c", "d"),
+            ("d", "e"),
+            ("x", "a"),
+        ]);
+        let a = graph.id(&"a").unwrap();
+        assert_eq!(labels(&graph, &graph.bfs(a)), vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(labels(&graph, &graph.dfs(a)), vec!["a", "b", "d", "e", "c"]);
+        assert_eq!(graph.neighbors(a).count(), 2);
+    }
+
+    #[test]
+    fn test_shortest_paths() {
+        let mut graph = Graph::undirected();
+        for (a, b, w) in [
+            ("a", "b", 4.0),
+            ("a", "c", 1.0),
+            ("c", "b", 2.0),
+            ("b", "d", 5.0),
+            ("e", "f", 1.0),
+        ] {
+            graph.add_edge(a, b, w);
+        }
+        let (a, d, e) = (
+            graph.id(&"a").unwrap(),
+            graph.id(&"d").unwrap(),
+            graph.id(&"e").unwrap(),
+        );
+        let dist = graph.dijkstra(a);
+        assert_eq!(dist[d], Some(8.0));
+        assert_eq!(dist[e], None);
+        let (len, path) = graph.shortest_path(a, d).unwrap();
+        assert_eq!(
+            (len, labels(&graph, &path)),
+            (8.0, vec!["a", "c", "b", "d"])
+        );
+        // undirected, so the way back is as long
+        assert_eq!(graph.shortest_path(d, a).unwrap().0, 8.0);
+        assert_eq!(graph.shortest_path(a, e), None);
+        assert_eq!(graph.shortest_path(a, a), Some((0.0, vec![a])));
+    }
+
+    #[test]
+    fn test_astar_matches_dijkstra() {
+        let mut grid = Graph::directed();
+        for x in 0..10i32 {
+            for y in 0..10i32 {
+                // some steps cost more, the heuristic stays admissible
+                let cost = 1.0 + ((x * 7 + y * 3) % 4) as f64;
+                for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                    if (0..10).contains(&nx) && (0..10).contains(&ny) && !(nx == 5 && ny < 8) {
+                        grid.add_edge((x, y), (nx, ny), cost);
+                    }
+                }
+            }
+        }
+        let from = grid.id(&(0, 0)).unwrap();
+        let dist = grid.dijkstra(from);
+        for (to, &expected) in dist.iter().enumerate() {
+            let (tx, ty) = *grid.label(to);
+            let h = |n: NodeId| {
+                let (x, y) = *grid.label(n);
+                ((x - tx).abs() + (y - ty).abs()) as f64
+            };
+            let found = grid.astar(from, to, h);
+            assert_eq!(found.as_ref().map(|f| f.0), expected);
+            if let Some((len, path)) = found {
+                let walked: f64 = path
+                    .windows(2)
+                    .map(|w| grid.neighbors(w[0]).find(|&(m, _)| m == w[1]).unwrap().1)
+                    .sum();
+                assert_eq!(walked, len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_topological_sort() {
+        let graph = directed(&[
+            ("std", "serde"),
+            ("serde", "csv"),
+            ("std", "csv"),
+            ("csv", "app"),
+        ]);
+        let order = graph.topological_sort().unwrap();
+        assert_eq!(labels(&graph, &order), vec!["std", "serde", "csv", "app"]);
+
+        let cyclic = directed(&[
+            ("a", "b"),
+            ("b", "c"),
+            ("c", "d"),
+            ("d", "b"),
+            ("d", "e"),
+            ("z", "a"),
+        ]);
+        let cycle = cyclic.topological_sort().unwrap_err();
+        let cycle = labels(&cyclic, &cycle);
+        assert_eq!(cycle.first(), cycle.last());
+        let mut members = cycle[1..].to_vec();
+        members.sort();
+        assert_eq!(members, vec!["b", "c", "d"]);
+        assert!(cycle.windows(2).all(|w| {
+            let from = cyclic.id(&w[0]).unwrap();
+            cyclic
+                .neighbors(from)
+                .any(|(m, _)| m == cyclic.id(&w[1]).unwrap())
+        }));
+
+        let self_loop = directed(&[("a", "a")]);
+        assert_eq!(
+            labels(&self_loop, &self_loop.topological_sort().unwrap_err()),
+            vec!["a", "a"]
+        );
+    }
+
+    #[test]
+    fn test_strongly_connected_components() {
+        let graph = directed(&[
+            ("a", "b"),
+            ("b", "c"),
+            ("c", "a"),
+            ("c", "d"),
+            ("d", "e"),
+            ("e", "d"),
+            ("f", "f"),
+        ]);
+        let sccs: Vec<_> = graph
+            .strongly_connected_components()
+            .iter()
+            .map(|c| labels(&graph, c))
+            .collect();
+        assert_eq!(sccs, vec![vec!["f"], vec!["a", "b", "c"], vec!["d", "e"]]);
+    }
+
+    #[test]
+    fn test_minimum_spanning_tree() {
+        let mut graph = Graph::undirected();
+        for (a, b, w) in [
+            ("a", "b", 7.0),
+            ("a", "d", 5.0),
+            ("b", "c", 8.0),
+            ("b", "d", 9.0),
+            ("b", "e", 7.0),
+            ("c", "e", 5.0),
+            ("d", "e", 15.0),
+            ("d", "f", 6.0),
+            ("e", "f", 8.0),
+            ("e", "g", 9.0),
+            ("f", "g", 11.0),
+            ("x", "y", 1.0),
+        ] {
+            graph.add_edge(a, b, w);
+        }
+        let tree = graph.minimum_spanning_tree();
+        assert_eq!(tree.len(), 7);
+        assert_eq!(tree.iter().map(|e| e.weight).sum::<f64>(), 39.0 + 1.0);
+    }
+
+    #[test]
+    fn test_csv_and_dot() {
+        let csv = "from,to,weight\nlibc, rand ,2.5\nrand,app,\n\"a \"\"b\"\"\",app,1\n";
+        let graph = Graph::from_csv(csv.as_bytes(), true).unwrap();
+        assert_eq!(graph.node_count(), 4);
+        assert_eq!(graph.edges()[1].weight, 1.0);
+        assert_eq!(
+            graph.to_dot(),
+            "digraph {\n    \"libc\";\n    \"rand\";\n    \"app\";\n    \"a \\\"b\\\"\";\n    \
+             \"libc\" -> \"rand\" [label=\"2.5\"];\n    \"rand\" -> \"app\";\n    \"a \\\"b\\\"\" -> \"app\";\n}\n"
+        );
+
+        let unweighted = Graph::from_csv("to,from\nb,a\n".as_bytes(), false).unwrap();
+        // columns go by header, not by position
+        assert_eq!(unweighted.format_path(&[0, 1]), "a -- b");
+        assert!(unweighted.to_dot().starts_with("graph {"));
+
+        match Graph::from_csv("from,to,weight\na,b,1\nb,c,-1\n".as_bytes(), true) {
+            Err(GraphError::InvalidWeight(3, w)) => assert_eq!(w, -1.0),
+            other => panic!("{:?}", other),
+        }
+        assert!(Graph::from_csv("from,to,weight\na,b,heavy\n".as_bytes(), true).is_err());
+        assert!(Graph::from_csv("source,target\na,b\n".as_bytes(), true).is_err());
+    }
+}
@@ -3,6 +3,7 @@ use std::path::PathBuf;
 use clap::{arg, command, value_parser, ArgAction, Command};
 mod cookbook;
 mod core;
+mod graphs;
 mod string_manip;
 mod trees;
 
@@ -20,6 +21,19 @@ fn main() {
         )
         .arg(arg!(-d --debug ... "Turn debugging information on"))
         .subcommand(Command::new("trees").about("Experiments with trees"))
+        .subcommand(
+            Command::new("graph")
+                .about("Graph algorithms on a CSV edge list (from,to[,weight]), a demo without one")
+                .arg(arg!([FILE]).value_parser(value_parser!(PathBuf)))
+                .arg(arg!(-u --undirected "edges go both ways"))
+                .arg(
+                    arg!(--path <NODE> "shortest path between two nodes")
+                        .num_args(2)
+                        .value_names(["FROM", "TO"])
+                        .requires("FILE"),
+                )
+                .arg(arg!(--dot "print the graph in Graphviz format").requires("FILE")),
+        )
         .subcommand(Command::new("algo").about("Algorithms from the Rust Cookbook"))
         .subcommand(Command::new("cmd").about("Command line from the Rust Cookbook"))
         .subcommand(
@@ -42,19 +56,416 @@ fn main() {
                 .about("Calculate sha256 for all files in given directory from the Rust Cookbook")
                 .arg(
                     arg!([DIR])
-                        .required(true)
+                        .required_unless_present("check")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(-c --check <FILE> "read checksums from the FILE and check them")
+                        .required(false)
+                        .conflicts_with("DIR")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(-a --algo <ALGO> "digest algorithm(s): sha1, sha256, sha384, sha512, fnv1a64")
+                        .required(false)
+                        .value_delimiter(',')
+                        .action(ArgAction::Append)
+                        .default_value("sha256")
+                        .value_parser(value_parser!(cookbook::cb_4_concurrency::DigestAlgo)),
+                )
+                .arg(
+                    arg!(--cache <FILE> "sqlite database with digests from previous runs [default: $XDG_CACHE_HOME/playground/hash_cache.db]")
+                        .required(false)
                         .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(--"no-cache" "always rehash every file").conflicts_with("rebuild-cache"))
+                .arg(arg!(--"rebuild-cache" "drop cached digests and rehash every file"))
+                .arg(
+                    arg!(--timeout <SECS> "stop hashing new files after SECS seconds")
+                        .required(false)
+                        .value_parser(parse_timeout),
                 ),
         )
         .subcommand(
             Command::new("thumbnails")
-                .about("Generate jpg thumbnails in parallel")
+                .about("Generate thumbnails for all images in a directory tree in parallel")
                 .arg(
                     arg!([DIR])
                         .required(true)
                         .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(-s --size <PX> "longest edge of a thumbnail")
+                        .default_value("300")
+                        .value_parser(value_parser!(u32).range(1..)),
+                )
+                .arg(
+                    arg!(-f --filter <FILTER> "nearest, triangle, catmullrom, gaussian or lanczos3")
+                        .default_value("lanczos3")
+                        .value_parser(cookbook::cb_4_concurrency::parse_filter),
+                )
+                .arg(
+                    arg!(--format <FORMAT> "output format e.g. jpg or png, keeps the source format by default")
+                        .required(false)
+                        .value_parser(|s: &str| {
+                            image::ImageFormat::from_extension(s).ok_or(format!("unknown format '{}'", s))
+                        }),
+                )
+                .arg(
+                    arg!(-o --output <DIR> "thumbnails directory, <DIR>/thumbnails by default")
+                        .required(false)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(--gallery "write <DIR>/index.html linking the thumbnails to the originals"))
+                .arg(
+                    arg!(--"contact-sheet" <FILE> "composite all thumbnails into a single PNG")
+                        .required(false)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--columns <N> "number of contact sheet columns")
+                        .required(false)
+                        .requires("contact-sheet")
+                        .value_parser(value_parser!(u32)),
+                )
+                .arg(
+                    arg!(--timeout <SECS> "stop generating new thumbnails after SECS seconds")
+                        .required(false)
+                        .value_parser(parse_timeout),
+                ),
+        )
+        .subcommand(
+            Command::new("fractal")
+                .about("Render a Julia or Mandelbrot set in parallel")
+                .arg(
+                    arg!(--width <PX> "image width")
+                        .default_value("1920")
+                        .value_parser(value_parser!(u32).range(1..)),
+                )
+                .arg(
+                    arg!(--height <PX> "image height")
+                        .default_value("1080")
+                        .value_parser(value_parser!(u32).range(1..)),
+                )
+                .arg(
+                    arg!(-i --iterations <N> "maximum number of iterations")
+                        .default_value("300")
+                        .value_parser(value_parser!(u32).range(1..)),
+                )
+                .arg(
+                    arg!(-c --constant <C> "constant of the Julia set, e.g. -0.8+0.156i")
+                        .default_value("-0.8+0.156i")
+                        .allow_hyphen_values(true)
+                        .value_parser(value_parser!(num::complex::Complex<f64>)),
+                )
+                .arg(
+                    arg!(--center <C> "viewport centre, e.g. -0.5+0i")
+                        .default_value("0+0i")
+                        .allow_hyphen_values(true)
+                        .value_parser(value_parser!(num::complex::Complex<f64>)),
+                )
+                .arg(arg!(-z --zoom <ZOOM> "zoom factor").default_value("1.0").value_parser(parse_zoom))
+                .arg(
+                    arg!(-m --mode <MODE> "julia or mandelbrot")
+                        .default_value("julia")
+                        .value_parser(value_parser!(cookbook::cb_4_concurrency::fractal::Mode)),
+                )
+                .arg(
+                    arg!(-p --palette <PALETTE> "red, grayscale, wavelength or fire")
+                        .default_value("red")
+                        .value_parser(value_parser!(cookbook::cb_4_concurrency::fractal::Palette)),
+                )
+                .arg(arg!(--smooth "colour by the normalised iteration count"))
+                .arg(
+                    arg!(--frames <N> "render a zoom animation with N frames")
+                        .default_value("1")
+                        .value_parser(value_parser!(u32).range(1..)),
+                )
+                .arg(
+                    arg!(--target <C> "point the animation zooms into, defaults to --center")
+                        .allow_hyphen_values(true)
+                        .value_parser(value_parser!(num::complex::Complex<f64>)),
+                )
+                .arg(
+                    arg!(--"end-zoom" <ZOOM> "zoom factor of the last frame")
+                        .default_value("1000.0")
+                        .value_parser(parse_zoom),
+                )
+                .arg(
+                    arg!(--"frame-delay" <MS> "delay between GIF frames")
+                        .default_value("40")
+                        .value_parser(value_parser!(u32)),
+                )
+                .arg(
+                    arg!(-o --output <FILE> "output image, .gif or numbered PNG frames for animations")
+                        .default_value("output.png")
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("bench")
+                .about("Benchmarks")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("concurrency")
+                        .about("Compare threadpool, rayon, crossbeam and std threads on the same workloads")
+                        .arg(
+                            arg!(-w --workload <NAME> "hash, julia or sort, all by default")
+                                .required(false)
+                                .value_delimiter(',')
+                                .action(ArgAction::Append)
+                                .value_parser(value_parser!(cookbook::cb_4_bench::Workload)),
+                        )
+                        .arg(
+                            arg!(-b --backend <NAME> "threadpool, rayon, crossbeam or std, all by default")
+                                .required(false)
+                                .value_delimiter(',')
+                                .action(ArgAction::Append)
+                                .value_parser(value_parser!(cookbook::cb_4_bench::Backend)),
+                        )
+                        .arg(
+                            arg!(-t --threads <N> "thread counts, 1,2,4,... up to the number of CPUs by default")
+                                .required(false)
+                                .value_delimiter(',')
+                                .action(ArgAction::Append)
+                                .value_parser(value_parser!(u64).range(1..)),
+                        )
+                        .arg(
+                            arg!(-r --repeat <N> "report the best of N runs")
+                                .default_value("3")
+                                .value_parser(value_parser!(u64).range(1..)),
+                        )
+                        .arg(
+                            arg!(--dir <DIR> "directory hashed by the hash workload")
+                                .default_value(".")
+                                .value_parser(value_parser!(PathBuf)),
+                        )
+                        .arg(
+                            arg!(--"sort-len" <N> "number of values sorted by the sort workload")
+                                .default_value("2000000")
+                                .value_parser(value_parser!(u64).range(1..)),
+                        )
+                        .arg(
+                            arg!(--csv <FILE> "also write the results as CSV")
+                                .required(false)
+                                .value_parser(value_parser!(PathBuf)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("heap")
+                        .about("Compare the indexed d-ary heap with BinaryHeap and lazy deletion")
+                        .arg(
+                            arg!(--nodes <N> "nodes of the random graph Dijkstra runs on")
+                                .default_value("200000")
+                                .value_parser(value_parser!(u64).range(1..)),
+                        )
+                        .arg(
+                            arg!(--updates <N> "priority changes of the job queue workload")
+                                .default_value("1000000")
+                                .value_parser(value_parser!(u64).range(1..)),
+                        )
+                        .arg(
+                            arg!(-a --arity <D> "arities of the indexed heap, 2,4,8 by default")
+                                .required(false)
+                                .value_delimiter(',')
+                                .action(ArgAction::Append)
+                                .value_parser(value_parser!(u64).range(2..)),
+                        )
+                        .arg(
+                            arg!(-r --repeat <N> "report the best of N runs")
+                                .default_value("3")
+                                .value_parser(value_parser!(u64).range(1..)),
+                        )
+                        .arg(
+                            arg!(--csv <FILE> "also write the results as CSV")
+                                .required(false)
+                                .value_parser(value_parser!(PathBuf)),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("vault")
+                .about("Encrypt files with a passphrase, read from $VAULT_PASSPHRASE or stdin")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("encrypt")
+                        .arg(arg!(<FILE> "file to encrypt").value_parser(value_parser!(PathBuf)))
+                        .arg(
+                            arg!(-o --output <FILE> "encrypted file, FILE.vault by default")
+                                .required(false)
+                                .value_parser(value_parser!(PathBuf)),
+                        )
+                        .arg(
+                            arg!(--cipher <CIPHER> "aes256gcm or chacha20poly1305")
+                                .default_value("aes256gcm")
+                                .value_parser(value_parser!(cookbook::cb_5_vault::Cipher)),
+                        )
+                        .arg(
+                            arg!(--iterations <N> "PBKDF2 iterations")
+                                .default_value("600000")
+                                .value_parser(value_parser!(std::num::NonZeroU32)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("decrypt")
+                        .arg(arg!(<FILE> "file to decrypt").value_parser(value_parser!(PathBuf)))
+                        .arg(
+                            arg!(-o --output <FILE> "decrypted file, FILE without .vault by default")
+                                .required(false)
+                                .value_parser(value_parser!(PathBuf)),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("sign")
+                .about("Write a detached HMAC signature FILE.sig")
+                .arg(
+                    arg!(-k --key <KEYFILE> "hex encoded secret key")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!([FILE] "file to sign")
+                        .required_unless_present("generate-key")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(--"generate-key" "create KEYFILE, readable only by its owner"))
+                .arg(
+                    arg!(-a --algo <ALGO> "hmac-sha256, hmac-sha384 or hmac-sha512")
+                        .default_value("hmac-sha256")
+                        .value_parser(value_parser!(cookbook::cb_5_cryptography::detached_hmac::HmacAlgo)),
                 ),
         )
+        .subcommand(
+            Command::new("verify")
+                .about("Check a detached HMAC signature written by sign")
+                .arg(
+                    arg!(-k --key <KEYFILE> "hex encoded secret key")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(<FILE> "signed file").value_parser(value_parser!(PathBuf)))
+                .arg(
+                    arg!(-s --signature <SIG> "signature file, FILE.sig by default")
+                        .required(false)
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("ed25519")
+                .about("Ed25519 public key signatures of files")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("keygen")
+                        .about("Write a PKCS#8 key pair to KEYFILE and its public key to KEYFILE.pub")
+                        .arg(arg!(<KEYFILE>).value_parser(value_parser!(PathBuf))),
+                )
+                .subcommand(
+                    Command::new("pubkey")
+                        .about("Print the public key of a key pair")
+                        .arg(arg!(<KEYFILE>).value_parser(value_parser!(PathBuf)))
+                        .arg(
+                            arg!(-f --format <FORMAT> "hex or base64")
+                                .default_value("hex")
+                                .value_parser(value_parser!(cookbook::cb_5_cryptography::ed25519::KeyFormat)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("sign")
+                        .about("Write a detached signature FILE.sig")
+                        .arg(
+                            arg!(-k --key <KEYFILE> "PKCS#8 key pair")
+                                .required(true)
+                                .value_parser(value_parser!(PathBuf)),
+                        )
+                        .arg(arg!(<FILE>).value_parser(value_parser!(PathBuf)))
+                        .arg(
+                            arg!(-s --signature <SIG> "signature file, FILE.sig by default")
+                                .required(false)
+                                .value_parser(value_parser!(PathBuf)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("verify")
+                        .about("Check a signature with the matching key from a keyring directory")
+                        .arg(
+                            arg!(--keyring <DIR> "directory with public keys in hex or base64")
+                                .required(true)
+                                .value_parser(value_parser!(PathBuf)),
+                        )
+                        .arg(arg!(<FILE>).value_parser(value_parser!(PathBuf)))
+                        .arg(
+                            arg!(-s --signature <SIG> "signature file, FILE.sig by default")
+                                .required(false)
+                                .value_parser(value_parser!(PathBuf)),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("otp")
+                .about("TOTP codes for two-factor authentication")
+                .arg(
+                    arg!(--uri <URI> "otpauth:// URI of the account")
+                        .required_unless_present_any(["secret", "new"])
+                        .conflicts_with_all(["secret", "new"]),
+                )
+                .arg(arg!(--secret <BASE32> "shared secret of a TOTP account").conflicts_with("new"))
+                .arg(
+                    arg!(--algo <ALGO> "SHA1, SHA256 or SHA512, for --secret and --new")
+                        .default_value("SHA1")
+                        .value_parser(value_parser!(cookbook::cb_5_otp::OtpAlgo)),
+                )
+                .arg(arg!(--new <ACCOUNT> "generate a secret and print its otpauth:// URI"))
+                .arg(arg!(--issuer <ISSUER> "issuer shown by authenticator apps").requires("new"))
+                .arg(arg!(--verify <CODE> "check a code instead of printing the current one"))
+                .arg(
+                    arg!(--window <STEPS> "accepted clock skew in time steps, at most 10")
+                        .default_value("1")
+                        .value_parser(value_parser!(u64).range(0..=10)),
+                ),
+        )
+        .subcommand(
+            Command::new("passgen")
+                .about("Random passwords and diceware passphrases")
+                .arg(
+                    arg!(-l --length <LEN> "password length")
+                        .default_value("20")
+                        .value_parser(value_parser!(u64).range(1..)),
+                )
+                .arg(
+                    arg!(--classes <CLASSES> "character classes, each one occurs at least once")
+                        .value_delimiter(',')
+                        .default_value("lower,upper,digit,symbol")
+                        .value_parser(value_parser!(cookbook::cb_1_passgen::CharClass)),
+                )
+                .arg(arg!(--"no-ambiguous" "leave out characters like l, 1, O and 0"))
+                .arg(
+                    arg!(-w --words <N> "passphrase of N words instead of a password")
+                        .requires("wordlist")
+                        .value_parser(value_parser!(u64).range(1..)),
+                )
+                .arg(
+                    arg!(--wordlist <FILE> "one word per line, diceware numbering is skipped")
+                        .requires("words")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(--separator <SEP> "between the passphrase words").default_value(" "))
+                .arg(
+                    arg!(-n --count <COUNT> "how many to generate")
+                        .default_value("1")
+                        .value_parser(value_parser!(u64).range(1..)),
+                ),
+        )
+        .subcommand(
+            Command::new("chmod")
+                .about("Change file permissions, absolute (640, rw-r-----) or symbolic (u+x,g-w)")
+                .arg(arg!(<MODE>))
+                .arg(
+                    arg!(<FILE> ...)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(-v --verbose "print the old and new permissions")),
+        )
         .subcommand(Command::new("db").about("Sqllite tests"))
         .subcommand(Command::new("datetime").about("Date and time experiments"))
         .subcommand(
@@ -66,9 +477,39 @@ fn main() {
         .subcommand(Command::new("enc").about("Encoding: character sets, CSVm structured data"))
         .subcommand(Command::new("err").about("Error handling"))
         .subcommand(Command::new("fs").about("File system"))
+        .subcommand(
+            Command::new("dedup")
+                .about("Find duplicate files by content")
+                .arg(
+                    arg!([DIR])
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(--hardlink "replace duplicates with hardlinks to the first copy"))
+                .arg(arg!(--delete "delete all copies but the first one").conflicts_with("hardlink"))
+                .arg(arg!(--apply "modify the files, without it only a dry run is done")),
+        )
+        .subcommand(Command::new("ds").about("Data structures: flags, bitsets, bloom filters, LRU cache, indexed heap"))
         .subcommand(Command::new("hardware").about("Hardware support"))
         .subcommand(Command::new("mem").about("Memory management"))
-        .subcommand(Command::new("net").about("Network e.g. tcp/ip server on loopback"))
+        .subcommand(
+            Command::new("net")
+                .about("Network e.g. tcp/ip server on loopback")
+                .subcommand(
+                    Command::new("chat")
+                        .about("End-to-end encrypted chat between two peers")
+                        .arg(
+                            arg!(--listen <ADDR> "wait for the peer, e.g. 127.0.0.1:0")
+                                .required_unless_present("connect")
+                                .conflicts_with("connect")
+                                .value_parser(value_parser!(std::net::SocketAddr)),
+                        )
+                        .arg(
+                            arg!(--connect <ADDR> "address of a listening peer")
+                                .value_parser(value_parser!(std::net::SocketAddr)),
+                        ),
+                ),
+        )
         .subcommand(Command::new("os").about("Operating system, calling commands"))
         .subcommand(Command::new("sci").about("Science - math: linear algebra, trigonometry, complex numbers, statistics, miscellaneous"))
         .subcommand(Command::new("text").about("Tex processing"))
@@ -96,10 +537,10 @@ fn main() {
         .get_one::<u8>("debug")
         .expect("Count's are defaulted")
     {
-        0 => println!("Debug mode is off"),
-        1 => println!("Debug mode is kind of on"),
-        2 => println!("Debug mode is on"),
-        _ => println!("Don't be crazy"),
+        0 => eprintln!("Debug mode is off"),
+        1 => eprintln!("Debug mode is kind of on"),
+        2 => eprintln!("Debug mode is on"),
+        _ => eprintln!("Don't be crazy"),
     }
 
     // You can check for the existence of subcommands, and if found use their
@@ -114,6 +555,41 @@ fn main() {
         }
     } else if matches.subcommand_matches("trees").is_some() {
         trees::main();
+    } else if let Some(matches) = matches.subcommand_matches("graph") {
+        use graphs::Graph;
+
+        let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+            graphs::main();
+            return;
+        };
+        let graph = match Graph::from_csv_path(path, !matches.get_flag("undirected")) {
+            Ok(graph) => graph,
+            Err(e) => {
+                eprintln!("graph: {}: {}", path.display(), e);
+                std::process::exit(2);
+            }
+        };
+        if let Some(mut nodes) = matches.get_many::<String>("path") {
+            let mut id = || {
+                let name = nodes.next().unwrap();
+                graph.id(name).unwrap_or_else(|| {
+                    eprintln!("graph: no node '{}'", name);
+                    std::process::exit(2);
+                })
+            };
+            let (from, to) = (id(), id());
+            match graph.shortest_path(from, to) {
+                Some((len, path)) => println!("{} ({})", graph.format_path(&path), len),
+                None => {
+                    println!("No path");
+                    std::process::exit(1);
+                }
+            }
+        } else if matches.get_flag("dot") {
+            print!("{}", graph.to_dot());
+        } else {
+            graphs::analyse(&graph);
+        }
     } else if matches.subcommand_matches("algo").is_some() {
         cookbook::cb_1_algorithms::main();
     } else if matches.subcommand_matches("cmd").is_some() {
@@ -129,12 +605,409 @@ fn main() {
     } else if matches.subcommand_matches("concurrency").is_some() {
         cookbook::cb_4_concurrency::main();
     } else if let Some(matches) = matches.subcommand_matches("sha256") {
-        if let Some(path) = matches.get_one::<PathBuf>("DIR") {
-            cookbook::cb_4_concurrency::sha256_files(path);
+        let algos: Vec<_> = matches
+            .get_many::<cookbook::cb_4_concurrency::DigestAlgo>("algo")
+            .unwrap_or_default()
+            .copied()
+            .collect();
+        if let Some(list) = matches.get_one::<PathBuf>("check") {
+            if !cookbook::cb_4_concurrency::sha256_check(list, algos[0]) {
+                std::process::exit(1);
+            }
+        } else if let Some(path) = matches.get_one::<PathBuf>("DIR") {
+            use cookbook::cb_7_database::hash_cache::HashCache;
+
+            let mut cache = None;
+            let cache_path = matches
+                .get_one::<PathBuf>("cache")
+                .cloned()
+                .or_else(HashCache::default_path)
+                .filter(|_| !matches.get_flag("no-cache"));
+            if let Some(cache_path) = cache_path {
+                if let Some(dir) = cache_path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                    let _ = std::fs::create_dir_all(dir);
+                }
+                match HashCache::open(&cache_path) {
+                    Ok(c) => cache = Some(c),
+                    Err(e) => eprintln!("Hash cache disabled, {}: {}", cache_path.display(), e),
+                }
+            }
+            if let Some(c) = cache.as_ref().filter(|_| matches.get_flag("rebuild-cache")) {
+                if let Err(e) = c.clear() {
+                    eprintln!("Could not clear the hash cache: {}", e);
+                }
+            }
+            let cancel = cancellation_token(matches);
+            let summary =
+                cookbook::cb_4_concurrency::sha256_files(path, &algos, cache.as_mut(), &cancel);
+            if let Some(reason) = summary.cancelled {
+                std::process::exit(reason.exit_code());
+            }
         }
     } else if let Some(matches) = matches.subcommand_matches("thumbnails") {
+        use cookbook::cb_4_concurrency::{generate_thumbnails_parallel, ThumbnailOptions};
+
+        let opts = ThumbnailOptions {
+            longest_edge: *matches.get_one("size").unwrap(),
+            filter: *matches.get_one("filter").unwrap(),
+            format: matches.get_one("format").copied(),
+            thumb_dir: matches.get_one("output").cloned(),
+        };
         if let Some(path) = matches.get_one::<PathBuf>("DIR") {
-            cookbook::cb_4_concurrency::generate_thumbnails_parallel(path);
+            use cookbook::cb_4_gallery::{contact_sheet, write_html_gallery};
+
+            let cancel = cancellation_token(matches);
+            let summary = generate_thumbnails_parallel(path, &opts, &cancel);
+            if matches.get_flag("gallery") {
+                match write_html_gallery(path, &summary.thumbnails) {
+                    Ok(index) => println!("Gallery written to {}", index.display()),
+                    Err(e) => eprintln!("Could not write the gallery: {}", e),
+                }
+            }
+            if let Some(sheet_path) = matches.get_one::<PathBuf>("contact-sheet") {
+                let columns = matches.get_one::<u32>("columns").copied();
+                match contact_sheet(&summary.thumbnails, opts.longest_edge, columns)
+                    .and_then(|sheet| sheet.save(sheet_path))
+                {
+                    Ok(_) => println!("Contact sheet saved to {}", sheet_path.display()),
+                    Err(e) => eprintln!("Could not create the contact sheet: {}", e),
+                }
+            }
+            if let Some(reason) = summary.cancelled {
+                std::process::exit(reason.exit_code());
+            } else if !summary.failures.is_empty() {
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(matches) = matches.subcommand_matches("fractal") {
+        use cookbook::cb_4_concurrency::fractal::{
+            render, render_zoom, FractalOptions, ZoomAnimation,
+        };
+
+        let opts = FractalOptions {
+            width: *matches.get_one("width").unwrap(),
+            height: *matches.get_one("height").unwrap(),
+            max_iterations: *matches.get_one("iterations").unwrap(),
+            c: *matches.get_one("constant").unwrap(),
+            center: *matches.get_one("center").unwrap(),
+            zoom: *matches.get_one("zoom").unwrap(),
+            mode: *matches.get_one("mode").unwrap(),
+            palette: *matches.get_one("palette").unwrap(),
+            smooth: matches.get_flag("smooth"),
+        };
+        let output = matches.get_one::<PathBuf>("output").unwrap();
+        let frames = *matches.get_one::<u32>("frames").unwrap();
+        let res = if frames > 1 {
+            let anim = ZoomAnimation {
+                target: *matches.get_one("target").unwrap_or(&opts.center),
+                end_zoom: *matches.get_one("end-zoom").unwrap(),
+                frames,
+                start: opts,
+            };
+            render_zoom(&anim, output, *matches.get_one("frame-delay").unwrap())
+        } else {
+            render(&opts).save(output)
+        };
+        match res {
+            Ok(_) => println!("Saved {}", output.display()),
+            Err(e) => eprintln!("Could not save {}: {}", output.display(), e),
+        }
+    } else if let Some(matches) = matches.subcommand_matches("bench") {
+        if let Some(matches) = matches.subcommand_matches("concurrency") {
+            use cookbook::cb_4_bench::{bench_concurrency, print_table, write_csv, BenchOptions};
+
+            let mut opts = BenchOptions {
+                repeat: *matches.get_one::<u64>("repeat").unwrap() as usize,
+                dir: matches.get_one::<PathBuf>("dir").unwrap().clone(),
+                sort_len: *matches.get_one::<u64>("sort-len").unwrap() as usize,
+                ..Default::default()
+            };
+            if let Some(workloads) = matches.get_many("workload") {
+                opts.workloads = workloads.copied().collect();
+            }
+            if let Some(backends) = matches.get_many("backend") {
+                opts.backends = backends.copied().collect();
+            }
+            if let Some(threads) = matches.get_many("threads") {
+                opts.threads = threads.map(|&t: &u64| t as usize).collect();
+            }
+
+            let results = bench_concurrency(&opts);
+            print_table(&results);
+            if let Some(csv) = matches.get_one::<PathBuf>("csv") {
+                match write_csv(csv, &results) {
+                    Ok(_) => println!("Results written to {}", csv.display()),
+                    Err(e) => eprintln!("Could not write {}: {}", csv.display(), e),
+                }
+            }
+        } else if let Some(matches) = matches.subcommand_matches("heap") {
+            use cookbook::cb_4_bench::{bench_heap, print_heap_table, write_csv, HeapBenchOptions};
+
+            let mut opts = HeapBenchOptions {
+                nodes: *matches.get_one::<u64>("nodes").unwrap() as usize,
+                updates: *matches.get_one::<u64>("updates").unwrap() as usize,
+                repeat: *matches.get_one::<u64>("repeat").unwrap() as usize,
+                ..Default::default()
+            };
+            if let Some(arities) = matches.get_many("arity") {
+                opts.arities = arities.map(|&d: &u64| d as usize).collect();
+            }
+
+            let results = bench_heap(&opts);
+            print_heap_table(&results);
+            if let Some(csv) = matches.get_one::<PathBuf>("csv") {
+                match write_csv(csv, &results) {
+                    Ok(_) => println!("Results written to {}", csv.display()),
+                    Err(e) => eprintln!("Could not write {}: {}", csv.display(), e),
+                }
+            }
+        }
+    } else if let Some(matches) = matches.subcommand_matches("vault") {
+        use cookbook::cb_5_vault::{decrypt_file, encrypt_file, read_passphrase, VaultOptions};
+
+        let res = match matches.subcommand() {
+            Some(("encrypt", matches)) => {
+                let file = matches.get_one::<PathBuf>("FILE").unwrap();
+                let out = matches
+                    .get_one::<PathBuf>("output")
+                    .cloned()
+                    .unwrap_or_else(|| {
+                        let mut name = file.clone().into_os_string();
+                        name.push(".vault");
+                        name.into()
+                    });
+                let opts = VaultOptions {
+                    cipher: *matches.get_one("cipher").unwrap(),
+                    iterations: *matches.get_one("iterations").unwrap(),
+                    ..Default::default()
+                };
+                read_passphrase(true)
+                    .map_err(Into::into)
+                    .and_then(|pass| encrypt_file(file, &out, pass.as_bytes(), &opts))
+                    .map(|_| out)
+            }
+            Some(("decrypt", matches)) => {
+                let file = matches.get_one::<PathBuf>("FILE").unwrap();
+                let out = match matches.get_one::<PathBuf>("output") {
+                    Some(out) => out.clone(),
+                    None if file.extension().is_some_and(|e| e == "vault") => {
+                        file.with_extension("")
+                    }
+                    None => {
+                        eprintln!("{} has no .vault extension, pass --output", file.display());
+                        std::process::exit(2);
+                    }
+                };
+                read_passphrase(false)
+                    .map_err(Into::into)
+                    .and_then(|pass| decrypt_file(file, &out, pass.as_bytes()))
+                    .map(|_| out)
+            }
+            _ => unreachable!("subcommand_required"),
+        };
+        match res {
+            Ok(out) => println!("Written {}", out.display()),
+            Err(e) => {
+                eprintln!("vault: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(matches) = matches.subcommand_matches("sign") {
+        use cookbook::cb_5_cryptography::detached_hmac::{generate_key, read_key, sign_file};
+
+        let key_path = matches.get_one::<PathBuf>("key").unwrap();
+        if matches.get_flag("generate-key") {
+            match generate_key(key_path) {
+                Ok(_) => println!("Key written to {}", key_path.display()),
+                Err(e) => {
+                    eprintln!("Could not create {}: {}", key_path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        if let Some(file) = matches.get_one::<PathBuf>("FILE") {
+            match read_key(key_path)
+                .and_then(|key| sign_file(&key, *matches.get_one("algo").unwrap(), file))
+            {
+                Ok(sig) => println!("Signature written to {}", sig.display()),
+                Err(e) => {
+                    eprintln!("Could not sign {}: {}", file.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    } else if let Some(matches) = matches.subcommand_matches("verify") {
+        use cookbook::cb_5_cryptography::detached_hmac::{read_key, signature_path, verify_file};
+
+        let file = matches.get_one::<PathBuf>("FILE").unwrap();
+        let sig = matches
+            .get_one::<PathBuf>("signature")
+            .cloned()
+            .unwrap_or_else(|| signature_path(file));
+        match read_key(matches.get_one::<PathBuf>("key").unwrap())
+            .and_then(|key| verify_file(&key, file, &sig))
+        {
+            Ok(true) => println!("{}: OK", file.display()),
+            Ok(false) => {
+                println!("{}: FAILED", file.display());
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Could not verify {}: {}", file.display(), e);
+                std::process::exit(2);
+            }
+        }
+    } else if let Some(matches) = matches.subcommand_matches("ed25519") {
+        use cookbook::cb_5_cryptography::detached_hmac::signature_path;
+        use cookbook::cb_5_cryptography::ed25519::{
+            export_public_key, generate_keypair, load_keypair, sign_file, verify_file,
+        };
+
+        let sig_path = |matches: &clap::ArgMatches, file: &PathBuf| {
+            matches
+                .get_one::<PathBuf>("signature")
+                .cloned()
+                .unwrap_or_else(|| signature_path(file))
+        };
+        let res = match matches.subcommand() {
+            Some(("keygen", matches)) => {
+                generate_keypair(matches.get_one::<PathBuf>("KEYFILE").unwrap())
+                    .map(|public| println!("Public key written to {}", public.display()))
+            }
+            Some(("pubkey", matches)) => {
+                load_keypair(matches.get_one::<PathBuf>("KEYFILE").unwrap()).map(|pair| {
+                    println!(
+                        "{}",
+                        export_public_key(&pair, *matches.get_one("format").unwrap())
+                    )
+                })
+            }
+            Some(("sign", matches)) => {
+                let file = matches.get_one::<PathBuf>("FILE").unwrap();
+                let sig = sig_path(matches, file);
+                load_keypair(matches.get_one::<PathBuf>("key").unwrap())
+                    .and_then(|pair| sign_file(&pair, file, &sig))
+                    .map(|_| println!("Signature written to {}", sig.display()))
+            }
+            Some(("verify", matches)) => {
+                let file = matches.get_one::<PathBuf>("FILE").unwrap();
+                let keyring = matches.get_one::<PathBuf>("keyring").unwrap();
+                match verify_file(keyring, file, &sig_path(matches, file)) {
+                    Ok(Some(key)) => {
+                        println!("{}: OK, signed by {}", file.display(), key.display());
+                        Ok(())
+                    }
+                    Ok(None) => {
+                        println!("{}: FAILED", file.display());
+                        std::process::exit(1);
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            _ => unreachable!("subcommand_required"),
+        };
+        if let Err(e) = res {
+            eprintln!("ed25519: {}", e);
+            std::process::exit(2);
+        }
+    } else if let Some(matches) = matches.subcommand_matches("otp") {
+        use cookbook::cb_5_otp::{decode_secret, OtpAuth};
+        use ring::rand::{SecureRandom, SystemRandom};
+
+        let algo = *matches.get_one("algo").unwrap();
+        let otp = if let Some(uri) = matches.get_one::<String>("uri") {
+            uri.parse::<OtpAuth>()
+        } else if let Some(secret) = matches.get_one::<String>("secret") {
+            decode_secret(secret).map(|s| OtpAuth {
+                algo,
+                ..OtpAuth::totp(s, "")
+            })
+        } else {
+            let account = matches.get_one::<String>("new").unwrap();
+            let mut secret = vec![0u8; 20];
+            SystemRandom::new()
+                .fill(&mut secret)
+                .expect("System randomness");
+            let otp = OtpAuth {
+                algo,
+                issuer: matches.get_one::<String>("issuer").cloned(),
+                ..OtpAuth::totp(secret, account)
+            };
+            println!("{}", otp.to_uri());
+            Ok(otp)
+        };
+        match otp {
+            Ok(otp) => match matches.get_one::<String>("verify") {
+                Some(code) => match otp.verify(code, *matches.get_one("window").unwrap()) {
+                    Some(_) => println!("{}: OK", code),
+                    None => {
+                        println!("{}: FAILED", code);
+                        std::process::exit(1);
+                    }
+                },
+                None => println!("{}", otp.code()),
+            },
+            Err(e) => {
+                eprintln!("otp: {}", e);
+                std::process::exit(2);
+            }
+        }
+    } else if let Some(matches) = matches.subcommand_matches("passgen") {
+        use cookbook::cb_1_passgen::{Passphrase, PasswordPolicy};
+
+        let count = *matches.get_one::<u64>("count").unwrap();
+        let res = if let Some(&words) = matches.get_one::<u64>("words") {
+            let words = words as usize;
+            let path = matches.get_one::<PathBuf>("wordlist").unwrap();
+            Passphrase::from_file(path)
+                .map(|list| {
+                    let separator = matches.get_one::<String>("separator").unwrap();
+                    for _ in 0..count {
+                        println!("{}", list.generate(words, separator));
+                    }
+                    list.entropy_bits(words)
+                })
+                .map_err(|e| format!("{}: {}", path.display(), e))
+        } else {
+            let policy = PasswordPolicy {
+                length: *matches.get_one::<u64>("length").unwrap() as usize,
+                classes: matches.get_many("classes").unwrap().copied().collect(),
+                exclude_ambiguous: matches.get_flag("no-ambiguous"),
+            };
+            policy.entropy_bits().and_then(|bits| {
+                for _ in 0..count {
+                    println!("{}", policy.generate()?);
+                }
+                Ok(bits)
+            })
+        };
+        match res {
+            // on stderr so the output can be piped
+            Ok(bits) => eprintln!("Entropy: {:.1} bits", bits),
+            Err(e) => {
+                eprintln!("passgen: {}", e);
+                std::process::exit(2);
+            }
+        }
+    } else if let Some(matches) = matches.subcommand_matches("chmod") {
+        use cookbook::cb_6_data_structures::permissions::chmod;
+
+        let mode = matches.get_one::<String>("MODE").unwrap();
+        let mut failed = false;
+        for path in matches.get_many::<PathBuf>("FILE").unwrap() {
+            match chmod(path, mode) {
+                Ok((old, new)) if matches.get_flag("verbose") => {
+                    println!("{}: {} -> {}", path.display(), old, new)
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("chmod: {}: {}", path.display(), e);
+                    failed = true;
+                }
+            }
+        }
+        if failed {
+            std::process::exit(1);
         }
     } else if matches.subcommand_matches("db").is_some() {
         cookbook::cb_7_database::main();
@@ -152,12 +1025,43 @@ fn main() {
         cookbook::cb_11_error_handling::main();
     } else if matches.subcommand_matches("fs").is_some() {
         cookbook::cb_12_filesystem::main();
+    } else if let Some(matches) = matches.subcommand_matches("dedup") {
+        use cookbook::cb_12_filesystem::find_duplicates::{run, Action};
+
+        let action = if matches.get_flag("hardlink") {
+            Action::Hardlink
+        } else if matches.get_flag("delete") {
+            Action::Delete
+        } else {
+            Action::Report
+        };
+        if let Some(path) = matches.get_one::<PathBuf>("DIR") {
+            if let Err(e) = run(path, action, !matches.get_flag("apply")) {
+                eprintln!("dedup: {}", e);
+                std::process::exit(2);
+            }
+        }
+    } else if matches.subcommand_matches("ds").is_some() {
+        cookbook::cb_6_data_structures::main();
     } else if matches.subcommand_matches("hardware").is_some() {
         cookbook::cb_13_hardware::main();
     } else if matches.subcommand_matches("mem").is_some() {
         cookbook::cb_14_mem_management::main();
-    } else if matches.subcommand_matches("net").is_some() {
-        cookbook::cb_15_network::main();
+    } else if let Some(matches) = matches.subcommand_matches("net") {
+        if let Some(matches) = matches.subcommand_matches("chat") {
+            use cookbook::cb_15_network::chat;
+
+            let res = match matches.get_one("listen") {
+                Some(&addr) => chat::listen(addr),
+                None => chat::connect(*matches.get_one("connect").unwrap()),
+            };
+            if let Err(e) = res {
+                eprintln!("chat: {}", e);
+                std::process::exit(2);
+            }
+        } else {
+            cookbook::cb_15_network::main();
+        }
     } else if matches.subcommand_matches("os").is_some() {
         cookbook::cb_16_os::main();
     } else if matches.subcommand_matches("sci").is_some() {
@@ -170,3 +1074,33 @@ fn main() {
         core::main::main();
     }
 }
+
+/// Fractal zoom factors, anything but a finite positive number renders nonsense
+fn parse_zoom(s: &str) -> Result<f64, String> {
+    let zoom: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if zoom.is_finite() && zoom > 0.0 {
+        Ok(zoom)
+    } else {
+        Err("must be a finite number greater than 0".to_string())
+    }
+}
+
+/// `--timeout` in seconds, fractions allowed
+fn parse_timeout(s: &str) -> Result<std::time::Duration, String> {
+    let secs: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    std::time::Duration::try_from_secs_f64(secs).map_err(|e| format!("{}", e))
+}
+
+/// Token cancelled by Ctrl-C and by the optional `--timeout` of the subcommand
+fn cancellation_token(matches: &clap::ArgMatches) -> cookbook::cb_4_cancel::CancellationToken {
+    use cookbook::cb_4_cancel::CancellationToken;
+
+    let cancel = match matches.get_one::<std::time::Duration>("timeout") {
+        Some(timeout) => CancellationToken::with_timeout(*timeout),
+        None => CancellationToken::new(),
+    };
+    if let Err(e) = cancel.cancel_on_ctrl_c() {
+        eprintln!("Could not install the Ctrl-C handler: {}", e);
+    }
+    cancel
+}